@@ -0,0 +1,250 @@
+//! # Inline SNES assembly
+//! This crate provides the [`asar_asm!`] and [`asar_asm_blocks!`] macros, which assemble their input with
+//! [asar-snes](<https://crates.io/crates/asar-snes>) at compile time and expand to the assembled bytes.
+//!
+//! The assembly can be written either as tokens or as a single string literal:
+//! ```rust
+//! use asar_snes_asm::asar_asm;
+//!
+//! let code: &[u8] = asar_asm!(org $008000 : lda #$01 : sta $19 : rtl);
+//! assert_eq!(code, [0xA9, 0x01, 0x85, 0x19, 0x6B]);
+//!
+//! let code: &[u8] = asar_asm!("org $008000 : lda #$01 : sta $19 : rtl");
+//! assert_eq!(code, [0xA9, 0x01, 0x85, 0x19, 0x6B]);
+//! ```
+//!
+//! When written as tokens, the spacing and line breaks of the original source are preserved, so multiple lines can be used instead of `:`.
+//! Since the tokens still have to be valid Rust tokens, comments (`;`) and some hex literals (e.g. `$1E`) are not supported in this form, use a string literal in that case.
+//! ```compile_fail
+//! # use asar_snes_asm::asar_asm;
+//! // `1E` is not a valid Rust literal, it is read as a float with an empty exponent
+//! let code: &[u8] = asar_asm!(org $008000 : lda #$1E);
+//! ```
+//! ```rust
+//! # use asar_snes_asm::asar_asm;
+//! let code: &[u8] = asar_asm!("org $008000 : lda #$1E");
+//! assert_eq!(code, [0xA9, 0x1E]);
+//! ```
+//!
+//! Errors reported by Asar are turned into compile errors, pointing at the line where they happened.
+//! ```compile_fail
+//! # use asar_snes_asm::asar_asm;
+//! let code: &[u8] = asar_asm!(
+//!     org $008000
+//!     lda #$01
+//!     not_an_opcode
+//! );
+//! ```
+//!
+//! Include paths are resolved relative to the directory of the crate that invokes the macro.
+use asar_snes as asar;
+use asar_snes::{AdvancedPatchOptions, PatchOption, PatchResult, RomData, WrittenBlock};
+use proc_macro::{Delimiter, Span, TokenStream, TokenTree};
+use quote::{quote, quote_spanned};
+
+#[cfg(test)]
+mod test;
+
+const PATCH_NAME: &str = "asar_asm.asm";
+
+/// Assembles the input and expands to a `&'static [u8]` with the bytes of all the written blocks, in the order Asar wrote them.
+///
+/// Usage
+/// ```rust
+/// use asar_snes_asm::asar_asm;
+///
+/// let code: &[u8] = asar_asm!(org $008000 : lda #$01 : sta $19 : rtl);
+/// assert_eq!(code, [0xA9, 0x01, 0x85, 0x19, 0x6B]);
+/// ```
+#[proc_macro]
+pub fn asar_asm(input: TokenStream) -> TokenStream {
+    match assemble(input) {
+        Ok(blocks) => {
+            let bytes = blocks.iter().flat_map(|(_, data)| data.iter());
+            TokenStream::from(quote! {
+                {
+                    const ASAR_ASM_BYTES: &[u8] = &[#(#bytes),*];
+                    ASAR_ASM_BYTES
+                }
+            })
+        }
+        Err(errors) => errors,
+    }
+}
+
+/// Assembles the input and expands to a `&'static [(u32, &'static [u8])]`, with the SNES address and the bytes of every written block.
+///
+/// Usage
+/// ```rust
+/// use asar_snes_asm::asar_asm_blocks;
+///
+/// let blocks: &[(u32, &[u8])] = asar_asm_blocks!(org $008000 : lda #$01 : org $00FFFC : dw $8000);
+/// assert_eq!(blocks, [(0x008000, &[0xA9, 0x01][..]), (0x00FFFC, &[0x00, 0x80][..])]);
+/// ```
+#[proc_macro]
+pub fn asar_asm_blocks(input: TokenStream) -> TokenStream {
+    match assemble(input) {
+        Ok(blocks) => {
+            let blocks = blocks.iter().map(|(address, data)| {
+                quote! { (#address, &[#(#data),*]) }
+            });
+            TokenStream::from(quote! {
+                {
+                    const ASAR_ASM_BLOCKS: &[(u32, &[u8])] = &[#(#blocks),*];
+                    ASAR_ASM_BLOCKS
+                }
+            })
+        }
+        Err(errors) => errors,
+    }
+}
+
+/// The assembly source reconstructed from the macro input, with the span of the first token of every line.
+///
+/// Generic over the span so that the line tracking can be tested outside of a macro invocation.
+struct Source<S> {
+    text: String,
+    line_spans: Vec<S>,
+    last: Option<(usize, usize)>,
+}
+
+impl<S: Copy> Source<S> {
+    fn new() -> Source<S> {
+        Source {
+            text: String::new(),
+            line_spans: Vec::new(),
+            last: None,
+        }
+    }
+
+    fn from_string(text: String, span: S) -> Source<S> {
+        let lines = text.lines().count().max(1);
+        Source {
+            text,
+            line_spans: vec![span; lines],
+            last: None,
+        }
+    }
+
+    /// Appends a token starting and ending at the given (line, column), keeping its distance to the previous token.
+    fn push_token(&mut self, text: &str, start: (usize, usize), end: (usize, usize), span: S) {
+        let (line, column) = start;
+        match self.last {
+            None => self.line_spans.push(span),
+            Some((last_line, _)) if line > last_line => {
+                self.text.push('\n');
+                self.line_spans.push(span);
+            }
+            Some((_, last_column)) if column > last_column => {
+                self.text
+                    .extend(std::iter::repeat_n(' ', column - last_column));
+            }
+            _ => {}
+        }
+        self.text.push_str(text);
+        self.last = Some(end);
+    }
+
+    /// Returns the span of the line of an error, if it comes from the macro input.
+    fn span_for(&self, filename: &str, line: i32) -> Option<S> {
+        if !filename.ends_with(PATCH_NAME) || line < 1 {
+            return None;
+        }
+        self.line_spans.get(line as usize - 1).copied()
+    }
+}
+
+impl Source<Span> {
+    fn from_tokens(input: TokenStream) -> Source<Span> {
+        let mut source = Source::new();
+        source.push_stream(input);
+        source
+    }
+
+    fn push_stream(&mut self, input: TokenStream) {
+        for tt in input {
+            match tt {
+                TokenTree::Group(group) => {
+                    let (open, close) = match group.delimiter() {
+                        Delimiter::Parenthesis => ("(", ")"),
+                        Delimiter::Bracket => ("[", "]"),
+                        Delimiter::Brace => ("{", "}"),
+                        Delimiter::None => ("", ""),
+                    };
+                    self.push_span(open, group.span_open());
+                    self.push_stream(group.stream());
+                    self.push_span(close, group.span_close());
+                }
+                tt => self.push_span(&tt.to_string(), tt.span()),
+            }
+        }
+    }
+
+    fn push_span(&mut self, text: &str, span: Span) {
+        let (start, end) = (span.start(), span.end());
+        self.push_token(
+            text,
+            (start.line(), start.column()),
+            (end.line(), end.column()),
+            span,
+        );
+    }
+}
+
+fn parse_source(input: TokenStream) -> Source<Span> {
+    let mut tokens = input.clone().into_iter();
+    if let (Some(TokenTree::Literal(literal)), None) = (tokens.next(), tokens.next()) {
+        if let Ok(string) = syn::parse::<syn::LitStr>(TokenTree::Literal(literal.clone()).into()) {
+            return Source::from_string(string.value(), literal.span());
+        }
+    }
+    Source::from_tokens(input)
+}
+
+fn assemble(input: TokenStream) -> Result<Vec<(u32, Vec<u8>)>, TokenStream> {
+    let source = parse_source(input);
+
+    let mut options = AdvancedPatchOptions::new().option(PatchOption::MemoryFile(
+        PATCH_NAME.into(),
+        source.text.clone().into(),
+    ));
+    if let Ok(manifest_dir) = std::env::var("CARGO_MANIFEST_DIR") {
+        options = options.option(PatchOption::Include(manifest_dir));
+    }
+    let rom = RomData::new(vec![0; asar::max_rom_size() as usize], 0);
+
+    let (result, written_blocks) = asar::with_asar_lock(|| {
+        let result = asar::patching::patch_ex(rom, PATCH_NAME, options);
+        let written_blocks = asar::patching::written_blocks();
+        (result, written_blocks)
+    });
+
+    match result {
+        PatchResult::Success(rom, _) => Ok(written_blocks
+            .iter()
+            .map(|block| (block.snesoffset as u32, block_bytes(&rom, block)))
+            .collect()),
+        PatchResult::Failure(errors) => {
+            let errors = errors.iter().map(|error| {
+                let message = &error.rawerrdata;
+                let span = source
+                    .span_for(&error.filename, error.line)
+                    .unwrap_or_else(Span::call_site);
+                let span = proc_macro2::Span::from(span);
+                quote_spanned! {span=> compile_error!(#message);}
+            });
+            Err(TokenStream::from(quote! {
+                {
+                    #(#errors)*
+                    &[]
+                }
+            }))
+        }
+    }
+}
+
+fn block_bytes(rom: &RomData, block: &WrittenBlock) -> Vec<u8> {
+    let start = block.pcoffset as usize;
+    let end = start + block.numbytes as usize;
+    rom.data[start..end].to_vec()
+}
@@ -0,0 +1,51 @@
+use crate::{Source, PATCH_NAME};
+
+/// Builds a source from (line, column, text) tokens, using the index of each token as its span.
+fn source(tokens: &[(usize, usize, &str)]) -> Source<usize> {
+    let mut source = Source::new();
+    for (index, &(line, column, text)) in tokens.iter().enumerate() {
+        source.push_token(text, (line, column), (line, column + text.len()), index);
+    }
+    source
+}
+
+#[test]
+fn test_source_lines() {
+    // org $008000
+    //     lda #$01 : rtl
+    let source = source(&[
+        (1, 0, "org"),
+        (1, 4, "$"),
+        (1, 5, "008000"),
+        (2, 4, "lda"),
+        (2, 8, "#"),
+        (2, 9, "$"),
+        (2, 10, "01"),
+        (2, 13, ":"),
+        (2, 15, "rtl"),
+    ]);
+    assert_eq!(source.text, "org $008000\nlda #$01 : rtl");
+    assert_eq!(source.line_spans, [0, 3]);
+}
+
+#[test]
+fn test_source_error_spans() {
+    let source = source(&[(1, 0, "org"), (2, 0, "nop"), (4, 0, "bogus")]);
+    assert_eq!(source.span_for(PATCH_NAME, 1), Some(0));
+    assert_eq!(source.span_for(PATCH_NAME, 3), Some(2));
+    assert_eq!(
+        source.span_for(&format!("/some/dir/{}", PATCH_NAME), 2),
+        Some(1)
+    );
+    // errors in included files, or without a line, point at the macro call
+    assert_eq!(source.span_for("include.asm", 1), None);
+    assert_eq!(source.span_for(PATCH_NAME, 0), None);
+    assert_eq!(source.span_for(PATCH_NAME, 4), None);
+}
+
+#[test]
+fn test_source_string() {
+    let source = Source::from_string("org $008000\nlda #$1E\nrtl".into(), 7);
+    assert_eq!(source.line_spans, [7, 7, 7]);
+    assert_eq!(source.span_for(PATCH_NAME, 2), Some(7));
+}
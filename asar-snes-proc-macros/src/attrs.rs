@@ -0,0 +1,27 @@
+use syn::{DeriveInput, LitStr, Path};
+
+/// Options shared by every `#[asar(...)]` container attribute.
+pub(crate) struct ContainerOptions {
+    pub(crate) krate: Path,
+    pub(crate) prefix: String,
+}
+
+pub(crate) fn parse_container_options(input: &DeriveInput) -> syn::Result<ContainerOptions> {
+    let mut options = ContainerOptions {
+        krate: syn::parse_quote!(::asar_snes),
+        prefix: String::new(),
+    };
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("asar")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                options.krate = meta.value()?.parse::<LitStr>()?.parse()?;
+            } else if meta.path.is_ident("prefix") {
+                options.prefix = meta.value()?.parse::<LitStr>()?.value();
+            } else {
+                return Err(meta.error("expected `crate` or `prefix`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
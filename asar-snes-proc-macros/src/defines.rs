@@ -0,0 +1,144 @@
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::{Data, DeriveInput, Fields, LitInt, LitStr};
+
+use crate::attrs::parse_container_options;
+
+/// Options of a single field in a `#[derive(AsarDefines)]` struct.
+struct FieldOptions {
+    rename: Option<String>,
+    hex: Option<usize>,
+    skip: bool,
+}
+
+fn parse_field_options(field: &syn::Field) -> syn::Result<FieldOptions> {
+    let mut options = FieldOptions {
+        rename: None,
+        hex: None,
+        skip: false,
+    };
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("asar")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                options.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("hex") {
+                options.hex = Some(if meta.input.peek(syn::Token![=]) {
+                    meta.value()?.parse::<LitInt>()?.base10_parse()?
+                } else {
+                    0
+                });
+            } else if meta.path.is_ident("skip") {
+                options.skip = true;
+            } else {
+                return Err(meta.error("expected `rename`, `hex` or `skip`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+pub(crate) fn derive_defines(input: DeriveInput) -> syn::Result<TokenStream> {
+    let options = parse_container_options(&input)?;
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "`AsarDefines` can only be derived for structs, use `DefineValue` for enums",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "`AsarDefines` can only be derived for structs with named fields",
+        ));
+    };
+    let krate = &options.krate;
+    let mut defines = Vec::new();
+    for field in &fields.named {
+        let field_options = parse_field_options(field)?;
+        if field_options.skip {
+            continue;
+        }
+        let ident = field.ident.as_ref().unwrap();
+        let name = format!(
+            "{}{}",
+            options.prefix,
+            field_options
+                .rename
+                .unwrap_or_else(|| ident.to_string().trim_start_matches("r#").to_string())
+        );
+        let value = match field_options.hex {
+            Some(width) => quote! { #krate::DefineValue::hex_define_value(&self.#ident, #width) },
+            None => quote! { #krate::DefineValue::define_value(&self.#ident) },
+        };
+        defines.push(quote! {
+            if let ::std::option::Option::Some(value) = #value {
+                defines.push(#krate::PatchOption::Define(::std::string::String::from(#name), value));
+            }
+        });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #krate::AsarDefines for #name #ty_generics #where_clause {
+            fn to_defines(&self) -> ::std::vec::Vec<#krate::PatchOption> {
+                let mut defines = ::std::vec::Vec::new();
+                #(#defines)*
+                defines
+            }
+        }
+    })
+}
+
+pub(crate) fn derive_define_value(input: DeriveInput) -> syn::Result<TokenStream> {
+    let options = parse_container_options(&input)?;
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "`DefineValue` can only be derived for enums",
+        ));
+    };
+    let krate = &options.krate;
+    let mut arms = Vec::new();
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new_spanned(
+                variant.to_token_stream(),
+                "`DefineValue` can only be derived for enums without fields",
+            ));
+        }
+        let mut rename = None;
+        for attr in variant.attrs.iter().filter(|a| a.path().is_ident("asar")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `rename`"))
+                }
+            })?;
+        }
+        let ident = &variant.ident;
+        arms.push(match rename {
+            Some(rename) => quote! {
+                Self::#ident => ::std::string::String::from(#rename),
+            },
+            None => quote! {
+                Self::#ident => ::std::string::ToString::to_string(&(Self::#ident as i64)),
+            },
+        });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #krate::DefineValue for #name #ty_generics #where_clause {
+            fn define_value(&self) -> ::std::option::Option<::std::string::String> {
+                ::std::option::Option::Some(match self {
+                    #(#arms)*
+                })
+            }
+        }
+    })
+}
@@ -0,0 +1,79 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Fields, LitStr};
+
+use crate::attrs::parse_container_options;
+
+fn parse_label_name(field: &syn::Field) -> syn::Result<Option<String>> {
+    let mut label = None;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("asar")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("label") {
+                label = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("expected `label`"))
+            }
+        })?;
+    }
+    Ok(label)
+}
+
+pub(crate) fn derive_from_labels(input: DeriveInput) -> syn::Result<TokenStream> {
+    let options = parse_container_options(&input)?;
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "`FromAsarLabels` can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "`FromAsarLabels` can only be derived for structs with named fields",
+        ));
+    };
+    let krate = &options.krate;
+
+    let mut lookups = Vec::new();
+    let mut initializers = Vec::new();
+    for (index, field) in fields.named.iter().enumerate() {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let label = format!(
+            "{}{}",
+            options.prefix,
+            parse_label_name(field)?
+                .unwrap_or_else(|| ident.to_string().trim_start_matches("r#").to_string())
+        );
+        let var = format_ident!("__field_{}", index);
+        lookups.push(quote! {
+            let #var = <#ty as #krate::LabelValue>::from_label_value(
+                labels.iter().find(|l| l.name == #label).map(|l| l.location),
+            );
+            if #var.is_none() {
+                missing.push(::std::string::String::from(#label));
+            }
+        });
+        initializers.push(quote! { #ident: #var.unwrap() });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #krate::FromAsarLabels for #name #ty_generics #where_clause {
+            fn from_labels(
+                labels: &[#krate::Label],
+            ) -> ::std::result::Result<Self, #krate::MissingLabelsError> {
+                let mut missing = ::std::vec::Vec::new();
+                #(#lookups)*
+                if !missing.is_empty() {
+                    return ::std::result::Result::Err(#krate::MissingLabelsError { labels: missing });
+                }
+                ::std::result::Result::Ok(Self {
+                    #(#initializers),*
+                })
+            }
+        }
+    })
+}
@@ -2,38 +2,174 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::parse_macro_input;
 
+mod attrs;
+mod defines;
+mod labels;
+
 /// This macro is used to ensure that the global lock is used in the function.
-/// 
-/// Usage 
+///
+/// Usage
 /// ```ignore
 /// use asar_snes::use_asar_global_lock;
-/// 
+///
 /// #[use_asar_global_lock]
 /// fn my_function() {
 ///    // code that uses multiple asar api calls in a multithreaded environment
 /// }
+///
+/// struct MyPatcher;
+///
+/// impl MyPatcher {
+///     #[use_asar_global_lock]
+///     fn apply<T: AsRef<str>>(&self, patch: T) -> bool
+///     where
+///         T: Clone,
+///     {
+///         // methods, generics and where clauses are supported as well
+///     }
+/// }
 /// ```
+///
+/// The attribute expands to a call to `::asar_snes::with_asar_lock`, if `asar_snes` was renamed or re-exported the path can be changed with
+/// `#[use_asar_global_lock(crate = "path::to::asar_snes")]`.
+///
+/// `async fn` and `const fn` are not supported, since the lock cannot be held across await points and it cannot be taken in a const context.
 /// # Note
 /// This attribute **only** does something if the `thread-safe` feature is **enabled**. Otherwise it is a no-op.
 #[proc_macro_attribute]
-pub fn use_asar_global_lock(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn use_asar_global_lock(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut krate: syn::Path = syn::parse_quote!(::asar_snes);
+    let attr_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("crate") {
+            krate = meta.value()?.parse::<syn::LitStr>()?.parse()?;
+            Ok(())
+        } else {
+            Err(meta.error("expected `crate`"))
+        }
+    });
+    parse_macro_input!(attr with attr_parser);
+
     let input_fn = parse_macro_input!(item as syn::ItemFn);
-    let fn_name = &input_fn.sig.ident;
-    let fn_block = &input_fn.block;
-    let fn_return_type = &input_fn.sig.output;
-    let fn_arguments = &input_fn.sig.inputs;
-    let fn_visilibility = &input_fn.vis;
-    let doc_comments = &input_fn.attrs;
-    let generics = &input_fn.sig.generics;
+    if let Some(asyncness) = &input_fn.sig.asyncness {
+        return syn::Error::new_spanned(
+            asyncness,
+            "`#[use_asar_global_lock]` cannot be used on `async fn`, the lock cannot be held across await points. Call `with_asar_lock` around the synchronous parts instead",
+        )
+        .into_compile_error()
+        .into();
+    }
+    if let Some(constness) = &input_fn.sig.constness {
+        return syn::Error::new_spanned(
+            constness,
+            "`#[use_asar_global_lock]` cannot be used on `const fn`, the lock cannot be taken in a const context",
+        )
+        .into_compile_error()
+        .into();
+    }
+
+    let attrs = &input_fn.attrs;
+    let vis = &input_fn.vis;
+    let sig = &input_fn.sig;
+    let block = &input_fn.block;
 
     let expanded = quote! {
-        #(#doc_comments)*
-        #fn_visilibility fn #fn_name #generics (#fn_arguments) #fn_return_type {
-            crate::with_asar_lock(|| {
-                #fn_block
-            })
+        #(#attrs)*
+        #vis #sig {
+            #krate::with_asar_lock(|| #block)
         }
     };
 
     TokenStream::from(expanded)
 }
+
+/// Derives `AsarDefines` for a struct, turning each field into a `PatchOption::Define`.
+///
+/// Every field has to implement `DefineValue`, which is implemented for integers (decimal), `bool` (`1`/`0`), strings and `Option<T>` (`None` fields are not defined).
+///
+/// Usage
+/// ```ignore
+/// use asar_snes::{AsarDefines, DefineValue};
+///
+/// #[derive(DefineValue)]
+/// enum Mode {
+///     Normal,
+///     #[asar(rename = "hard")]
+///     Hard,
+/// }
+///
+/// #[derive(AsarDefines)]
+/// #[asar(prefix = "cfg_")]
+/// struct Config {
+///     #[asar(hex = 6)]
+///     freespace: u32, // !cfg_freespace = $108000
+///     lives: u8, // !cfg_lives = 5
+///     #[asar(rename = "sa1")]
+///     use_sa1: bool, // !cfg_sa1 = 1
+///     mode: Mode, // !cfg_mode = hard
+///     #[asar(skip)]
+///     comment: String,
+/// }
+///
+/// let options = AdvancedPatchOptions::new().options(config.to_defines());
+/// ```
+///
+/// # Attributes
+/// - `#[asar(prefix = "...")]` on the struct: prefix added to every define name.
+/// - `#[asar(crate = "...")]` on the struct: path to the `asar_snes` crate, if it was renamed or re-exported.
+/// - `#[asar(rename = "...")]` on a field: name of the define, instead of the field name.
+/// - `#[asar(hex)]` or `#[asar(hex = width)]` on a field: formats the value as a `$`-prefixed hex number, zero-padded to `width` digits.
+/// - `#[asar(skip)]` on a field: the field is not turned into a define.
+#[proc_macro_derive(AsarDefines, attributes(asar))]
+pub fn derive_asar_defines(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as syn::DeriveInput);
+    defines::derive_defines(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `DefineValue` for an enum without fields, so it can be used as a field of an `AsarDefines` struct.
+///
+/// By default a variant is turned into its discriminant, `#[asar(rename = "...")]` on a variant makes it use the provided text instead.
+///
+/// `#[asar(crate = "...")]` on the enum can be used to provide the path to the `asar_snes` crate, if it was renamed or re-exported.
+#[proc_macro_derive(DefineValue, attributes(asar))]
+pub fn derive_define_value(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as syn::DeriveInput);
+    defines::derive_define_value(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `FromAsarLabels` for a struct, filling each field with the value of the label of the same name.
+///
+/// Every field has to implement `LabelValue`, which is implemented for integers and `Option<T>`.
+/// Labels for `Option<T>` fields are optional, every other label is required and if any of them is missing
+/// a `MissingLabelsError` listing all of them is returned.
+///
+/// Usage
+/// ```ignore
+/// use asar_snes::FromAsarLabels;
+///
+/// #[derive(FromAsarLabels)]
+/// struct Addresses {
+///     main: u32,
+///     #[asar(label = "main_loop")]
+///     game_loop: u32,
+///     debug_hook: Option<u32>,
+/// }
+///
+/// let result = patcher.apply(rom, "patch.asm")?;
+/// let addresses = Addresses::from_labels(&result.labels())?;
+/// ```
+///
+/// # Attributes
+/// - `#[asar(prefix = "...")]` on the struct: prefix added to every label name.
+/// - `#[asar(crate = "...")]` on the struct: path to the `asar_snes` crate, if it was renamed or re-exported.
+/// - `#[asar(label = "...")]` on a field: name of the label, instead of the field name.
+#[proc_macro_derive(FromAsarLabels, attributes(asar))]
+pub fn derive_from_asar_labels(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as syn::DeriveInput);
+    labels::derive_from_labels(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
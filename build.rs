@@ -1,6 +1,6 @@
 use cmake::Config;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 fn make_lib_name(name: &str) -> String {
     if cfg!(target_os = "windows") {
@@ -10,16 +10,19 @@ fn make_lib_name(name: &str) -> String {
     }
 }
 
-fn main() {
-    println!("cargo:rerun-if-changed=build.rs");
-    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
-    let target = env::var("TARGET").unwrap();
+fn link_cpp_runtime(target: &str) {
+    if target.contains("linux") {
+        println!("cargo:rustc-link-lib=dylib=stdc++");
+    }
+}
 
+/// Builds Asar from the submodule with cmake and links it statically.
+fn build_bundled_asar(out_dir: &Path, target: &str) {
     let expected_lib_path = out_dir.join("lib").join(make_lib_name("asar-static"));
     // build asar with cmake
     if !expected_lib_path.exists() {
         let _dst = Config::new("src/asar/src")
-            .out_dir(out_dir.clone())
+            .out_dir(out_dir)
             .define("ASAR_GEN_LIB", "ON")
             .define("ASAR_GEN_EXE", "OFF")
             .define("ASAR_GEN_DLL", "OFF")
@@ -29,16 +32,39 @@ fn main() {
             .build();
     }
 
-    println!("cargo:rerun-if-changed=src/asar/src/asar-dll-bindings/c/asar.h");
+    link_cpp_runtime(target);
+    println!("cargo:rustc-link-search={}", out_dir.join("lib").display());
+    println!("cargo:rustc-link-lib=static=asar-static");
+}
 
-    if target.contains("linux") {
-        println!("cargo:rustc-link-lib=dylib=stdc++");
+/// Links an Asar library that is already installed, from `ASAR_LIB_DIR` or found with pkg-config.
+fn link_system_asar(target: &str) {
+    println!("cargo:rerun-if-env-changed=ASAR_LIB_DIR");
+    let Some(lib_dir) = env::var_os("ASAR_LIB_DIR") else {
+        pkg_config::Config::new()
+            .probe("asar")
+            .expect("Asar was not found with pkg-config, set ASAR_LIB_DIR to the directory containing libasar or asar-static");
+        return;
+    };
+    let lib_dir = PathBuf::from(lib_dir);
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+    // prefer the static library, like the bundled build
+    if lib_dir.join(make_lib_name("asar-static")).exists() {
+        link_cpp_runtime(target);
+        println!("cargo:rustc-link-lib=static=asar-static");
+    } else {
+        println!("cargo:rustc-link-lib=dylib=asar");
     }
+}
+
+#[cfg(feature = "bindgen")]
+fn generate_bindings(out_dir: &Path, dynamic: bool) {
+    println!("cargo:rerun-if-changed=src/asar/src/asar-dll-bindings/c/asar.h");
 
     // The bindgen::Builder is the main entry point
     // to bindgen, and lets you build up options for
     // the resulting bindings.
-    let bindings = bindgen::Builder::default()
+    let mut builder = bindgen::Builder::default()
         // The input header we would like to generate
         // bindings for.
         .header("src/asar/src/asar-dll-bindings/c/asar.h")
@@ -56,17 +82,41 @@ fn main() {
         .allowlist_function("asar_.*")
         .default_enum_style(bindgen::EnumVariation::Rust {
             non_exhaustive: false,
-        })
+        });
+    if dynamic {
+        // generate a struct holding the symbols of a loaded library instead of extern functions
+        builder = builder
+            .dynamic_library_name("AsarLibrary")
+            .dynamic_link_require_all(true);
+    }
+    let bindings = builder
         // Finish the builder and generate the bindings.
         .generate()
         // Unwrap the Result and panic on failure.
         .expect("Unable to generate bindings");
 
-    println!("cargo:rustc-link-search={}", out_dir.join("lib").display());
-    println!("cargo:rustc-link-lib=static=asar-static");
-
     // Write the bindings to the $OUT_DIR/bindings.rs file.
     bindings
         .write_to_file(out_dir.join("bindings.rs"))
         .expect("Couldn't write bindings!");
 }
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let target = env::var("TARGET").unwrap();
+    // with the dynamic feature the library is loaded at runtime, so there is nothing to build nor link
+    let dynamic = env::var_os("CARGO_FEATURE_DYNAMIC").is_some();
+
+    if !dynamic {
+        if env::var_os("CARGO_FEATURE_SYSTEM_ASAR").is_some() {
+            link_system_asar(&target);
+        } else {
+            build_bundled_asar(&out_dir, &target);
+        }
+    }
+
+    // without the bindgen feature, the bindings checked in src/ are used
+    #[cfg(feature = "bindgen")]
+    generate_bindings(&out_dir, dynamic);
+}
@@ -0,0 +1,327 @@
+//! A dedicated Asar thread, as an alternative to calling Asar from every thread with the global lock.
+//!
+//! An [`AsarHandle`] sends jobs, closures or typed commands like [`AsarHandle::patch_ex`], to a single thread that
+//! owns Asar, so Asar's state is only ever touched by that thread. Asar is reset after each job, and a job that
+//! panics fails with [`JobPanicked`] without affecting the following ones.
+//!
+//! Jobs wait in a bounded queue and run one at a time, in the order they entered the queue. Submitting a job while
+//! the queue is full waits for room, and the futures of the `async` feature are woken by the Asar thread, so they
+//! don't depend on any executor.
+//!
+//! e.g.
+//! ```rust,no_run
+//! use asar_snes::actor::AsarHandle;
+//! use asar_snes::{AdvancedPatchOptions, PatchResult};
+//!
+//! let asar = AsarHandle::new();
+//! let handles = ["a.asm", "b.asm"].map(|patch| {
+//!     let asar = asar.clone();
+//!     std::thread::spawn(move || asar.patch_ex(vec![0; 0x80000].into(), patch, AdvancedPatchOptions::new()))
+//! });
+//! for handle in handles {
+//!     match handle.join().unwrap() {
+//!         Ok(PatchResult::Success(rom, _)) => println!("assembled, {} bytes", rom.length),
+//!         Ok(PatchResult::Failure(errors)) => println!("{:?}", errors),
+//!         Err(panicked) => println!("{}", panicked),
+//!     }
+//! }
+//! ```
+use std::{
+    any::Any,
+    cell::Cell,
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::{pin, Pin},
+    sync::{Arc, Condvar, Mutex, OnceLock},
+    task::{Context, Poll, Wake, Waker},
+    thread,
+};
+
+use crate::{patching, AdvancedPatchOptions, ApplyOutput, PatchResult, Patcher, RomData};
+
+/// The maximum number of jobs waiting for the Asar thread, submitting more jobs waits until one is taken.
+pub(crate) const QUEUE_CAPACITY: usize = 32;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+thread_local! {
+    static ON_ASAR_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Error returned when a job panicked, Asar was reset and the next jobs are not affected.
+pub struct JobPanicked {
+    payload: Box<dyn Any + Send>,
+}
+
+impl JobPanicked {
+    /// Returns the panic message, if it is a string.
+    pub fn message(&self) -> Option<&str> {
+        match self.payload.downcast_ref::<&str>() {
+            Some(message) => Some(message),
+            None => self.payload.downcast_ref::<String>().map(String::as_str),
+        }
+    }
+
+    /// Returns the panic payload, e.g. to resume the panic with [`std::panic::resume_unwind`].
+    pub fn into_panic(self) -> Box<dyn Any + Send> {
+        self.payload
+    }
+}
+
+impl fmt::Debug for JobPanicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobPanicked")
+            .field("message", &self.message())
+            .finish()
+    }
+}
+
+impl fmt::Display for JobPanicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.message() {
+            Some(message) => write!(f, "Asar job panicked: {}", message),
+            None => write!(f, "Asar job panicked"),
+        }
+    }
+}
+
+impl std::error::Error for JobPanicked {}
+
+/// A handle to the Asar thread, cheap to clone and to send to other threads.
+///
+/// All the handles share the same thread, since Asar's state is global to the process.
+///
+/// See the [module documentation](self) for more information.
+#[derive(Debug, Clone, Default)]
+pub struct AsarHandle {
+    _private: (),
+}
+
+struct Queue {
+    state: Mutex<QueueState>,
+    job_available: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    jobs: VecDeque<Job>,
+    /// The tasks waiting for room in the queue.
+    blocked: Vec<Waker>,
+}
+
+/// Returns the queue of the Asar thread, starting the thread on the first call.
+fn queue() -> &'static Queue {
+    static QUEUE: OnceLock<Queue> = OnceLock::new();
+    QUEUE.get_or_init(|| {
+        thread::Builder::new()
+            .name("asar".into())
+            .spawn(|| {
+                ON_ASAR_THREAD.set(true);
+                queue().run()
+            })
+            .expect("failed to spawn the Asar thread");
+        Queue {
+            state: Mutex::new(QueueState::default()),
+            job_available: Condvar::new(),
+        }
+    })
+}
+
+impl Queue {
+    fn run(&self) {
+        loop {
+            let job = {
+                let mut state = self.state.lock().unwrap();
+                loop {
+                    if let Some(job) = state.jobs.pop_front() {
+                        state.blocked.drain(..).for_each(Waker::wake);
+                        break job;
+                    }
+                    state = self.job_available.wait(state).unwrap();
+                }
+            };
+            job();
+        }
+    }
+}
+
+/// Pushes a job to the queue, waiting while the queue is full.
+struct Enqueue {
+    job: Option<Job>,
+}
+
+impl Future for Enqueue {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let queue = queue();
+        let mut state = queue.state.lock().unwrap();
+        if state.jobs.len() < QUEUE_CAPACITY {
+            state
+                .jobs
+                .push_back(self.job.take().expect("polled after completion"));
+            queue.job_available.notify_one();
+            Poll::Ready(())
+        } else {
+            state.blocked.push(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+struct Slot<T> {
+    value: Option<Result<T, JobPanicked>>,
+    waker: Option<Waker>,
+}
+
+/// Waits for the value of a job.
+struct Reply<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> Future for Reply<T> {
+    type Output = Result<T, JobPanicked>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock().unwrap();
+        match slot.value.take() {
+            Some(value) => Poll::Ready(value),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Runs `f` with the global lock, then resets Asar, even if `f` panicked.
+///
+/// A panic while taking the lock, e.g. with an incompatible Asar library, also fails the job instead of the thread.
+fn run_job<F, T>(f: F) -> Result<T, JobPanicked>
+where
+    F: FnOnce() -> T,
+{
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        // the lock resets Asar itself if `f` panics
+        crate::with_asar_lock(|| {
+            let value = f();
+            patching::reset();
+            value
+        })
+    }))
+    .map_err(|payload| JobPanicked { payload })
+}
+
+/// Queues `f` when first polled and waits for its value.
+async fn submit<F, T>(f: F) -> Result<T, JobPanicked>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let slot = Arc::new(Mutex::new(Slot {
+        value: None,
+        waker: None,
+    }));
+    let reply = Arc::clone(&slot);
+    let job: Job = Box::new(move || {
+        let value = run_job(f);
+        let mut slot = reply.lock().unwrap();
+        slot.value = Some(value);
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+    });
+    Enqueue { job: Some(job) }.await;
+    Reply { slot }.await
+}
+
+/// Runs a future to completion, parking the current thread while it is pending.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(value) = future.as_mut().poll(&mut cx) {
+            return value;
+        }
+        thread::park();
+    }
+}
+
+impl AsarHandle {
+    /// Creates a new handle to the Asar thread, the thread is started by the first job.
+    pub fn new() -> AsarHandle {
+        AsarHandle { _private: () }
+    }
+
+    /// Runs `f` on the Asar thread and returns its value, blocking the calling thread.
+    ///
+    /// When called from a job, `f` runs immediately instead of being queued, and Asar is not reset. It must not be called while holding the
+    /// global lock, e.g. with a live [`ApplyResult`](crate::ApplyResult), since the Asar thread would wait for it forever.
+    ///
+    /// remarks: This function uses the global lock on the Asar thread.
+    pub fn run<F, T>(&self, f: F) -> Result<T, JobPanicked>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        if ON_ASAR_THREAD.get() {
+            // the outer job resets Asar once it is done
+            return panic::catch_unwind(AssertUnwindSafe(f))
+                .map_err(|payload| JobPanicked { payload });
+        }
+        block_on(submit(f))
+    }
+
+    /// Runs `f` on the Asar thread, returning a future of its value.
+    ///
+    /// The job is queued when the future is first polled.
+    ///
+    /// remarks: This function uses the global lock on the Asar thread.
+    #[cfg(feature = "async")]
+    pub fn run_async<F, T>(&self, f: F) -> impl Future<Output = Result<T, JobPanicked>> + Send
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        submit(f)
+    }
+
+    /// Patches the ROM data like [`patching::patch_ex`], on the Asar thread.
+    ///
+    /// Since Asar is reset after each job, [`PatchOption::ShouldReset`](crate::PatchOption::ShouldReset) has no effect.
+    ///
+    /// remarks: This function uses the global lock on the Asar thread.
+    pub fn patch_ex<T: Into<String>>(
+        &self,
+        rom: RomData,
+        patch: T,
+        options: AdvancedPatchOptions,
+    ) -> Result<PatchResult, JobPanicked> {
+        let patch = patch.into();
+        self.run(move || patching::patch_ex(rom, patch, options))
+    }
+
+    /// Applies the patch like [`Patcher::apply`], on the Asar thread, gathering all the information about the result.
+    ///
+    /// remarks: This function uses the global lock on the Asar thread.
+    pub fn apply<T: Into<String>>(
+        &self,
+        patcher: Patcher,
+        rom: RomData,
+        patch: T,
+    ) -> Result<ApplyOutput, JobPanicked> {
+        let patch = patch.into();
+        self.run(move || patcher.apply_output(rom, patch))
+    }
+}
@@ -0,0 +1,734 @@
+//! Language server for Asar assembly, built on top of [`asar_snes::patching::patch_ex`].
+//!
+//! Every time a document changes, the patch is assembled again with the unsaved documents passed as memory files,
+//! the errors and warnings are published as diagnostics, and the labels and defines of the last successful build
+//! are used for go-to-definition, hover and completion.
+//!
+//! The server reads these initialization options, all optional:
+//! ```json
+//! {
+//!     "entry": "main.asm",
+//!     "rom": "base.sfc",
+//!     "includePaths": ["include"],
+//!     "defines": { "DEBUG": "1" }
+//! }
+//! ```
+//! Relative paths are resolved from the workspace root. Without an entry, the document that changed is assembled on its own.
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use asar_snes as asar;
+use asar_snes::{
+    deps, AdvancedPatchOptions, Define, ErrorData, Label, PatchOption, PatchResult, RomData,
+};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+        Notification as _, PublishDiagnostics,
+    },
+    request::{Completion, GotoDefinition, HoverRequest, Request as _},
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, GotoDefinitionParams, GotoDefinitionResponse, Hover,
+    HoverContents, HoverParams, HoverProviderCapability, InitializeParams, Location, MarkupContent,
+    MarkupKind, NumberOrString, OneOf, Position, PublishDiagnosticsParams, Range,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use serde_json::Value;
+
+/// Settings read from the initialization options.
+#[derive(Debug, Default)]
+struct Config {
+    root: PathBuf,
+    entry: Option<PathBuf>,
+    rom: Option<PathBuf>,
+    include_paths: Vec<PathBuf>,
+    defines: Vec<(String, String)>,
+}
+
+/// The symbols of the last successful build.
+#[derive(Debug, Default)]
+struct Symbols {
+    labels: Vec<Label>,
+    defines: Vec<Define>,
+    files: Vec<PathBuf>,
+}
+
+struct Server {
+    connection: Connection,
+    config: Config,
+    documents: HashMap<PathBuf, String>,
+    symbols: Symbols,
+    /// Files that currently have diagnostics, so that they can be cleared after the next build.
+    diagnosed: HashSet<PathBuf>,
+}
+
+impl Config {
+    fn from_params(params: &InitializeParams) -> Config {
+        #[allow(deprecated)]
+        let root = params
+            .workspace_folders
+            .as_ref()
+            .and_then(|folders| folders.first())
+            .map(|folder| &folder.uri)
+            .or(params.root_uri.as_ref())
+            .and_then(|uri| uri.to_file_path().ok())
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+
+        let options = params.initialization_options.as_ref();
+        let path = |key: &str| {
+            options
+                .and_then(|o| o.get(key))
+                .and_then(Value::as_str)
+                .map(|p| deps::normalize(&root.join(p)))
+        };
+        let entry = path("entry");
+        let rom = path("rom");
+        let include_paths = options
+            .and_then(|o| o.get("includePaths"))
+            .and_then(Value::as_array)
+            .map(|paths| {
+                paths
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(|p| deps::normalize(&root.join(p)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let defines = options
+            .and_then(|o| o.get("defines"))
+            .and_then(Value::as_object)
+            .map(|defines| {
+                defines
+                    .iter()
+                    .map(|(name, value)| {
+                        let value = match value {
+                            Value::String(s) => s.clone(),
+                            value => value.to_string(),
+                        };
+                        (name.clone(), value)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Config {
+            root,
+            entry,
+            rom,
+            include_paths,
+            defines,
+        }
+    }
+}
+
+/// Converts a character offset in UTF-16 code units, as used by LSP, to a byte offset in `line`.
+fn byte_offset(line: &str, character: u32) -> usize {
+    let mut units = 0;
+    for (offset, c) in line.char_indices() {
+        if units >= character as usize {
+            return offset;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '!'
+}
+
+/// Returns the word under the cursor, including the leading `!` of defines and `.` of sublabels.
+fn word_at(text: &str, position: Position) -> Option<&str> {
+    let line = text.lines().nth(position.line as usize)?;
+    let offset = byte_offset(line, position.character);
+    let start = line[..offset]
+        .rfind(|c| !is_word_char(c))
+        .map_or(0, |i| i + 1);
+    let end = line[offset..]
+        .find(|c| !is_word_char(c))
+        .map_or(line.len(), |i| offset + i);
+    let word = line[start..end].trim_end_matches('.');
+    (!word.is_empty()).then_some(word)
+}
+
+/// Returns the name of the closest top-level label before `line`, used to resolve sublabels.
+fn parent_label(text: &str, line: usize) -> Option<&str> {
+    let lines = text.lines().take(line + 1).collect::<Vec<_>>();
+    lines.into_iter().rev().find_map(|l| {
+        let name = l.split_once(':')?.0;
+        (!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+            .then_some(name)
+    })
+}
+
+/// Returns whether `line` defines the label `name`, either as `name:` or `name = value`.
+fn defines_label(line: &str, name: &str) -> bool {
+    let Some(rest) = line.trim_start().strip_prefix(name) else {
+        return false;
+    };
+    rest.starts_with(':') || rest.trim_start().starts_with('=')
+}
+
+/// Returns whether `line` defines the sublabel `name`, which Asar names `Parent_sub` but is written `.sub`.
+fn defines_sublabel(text: &str, index: usize, line: &str, name: &str) -> bool {
+    let Some(rest) = line.trim_start().strip_prefix('.') else {
+        return false;
+    };
+    let sub = rest
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .next()
+        .unwrap_or_default();
+    !sub.is_empty()
+        && parent_label(text, index).is_some_and(|parent| format!("{}_{}", parent, sub) == name)
+}
+
+/// Returns whether `line` assigns the define `name`.
+fn defines_define(line: &str, name: &str) -> bool {
+    let Some(rest) = line
+        .trim_start()
+        .strip_prefix('!')
+        .and_then(|l| l.strip_prefix(name))
+    else {
+        return false;
+    };
+    let rest = rest.trim_start();
+    ["=", "+=", ":=", "#=", "?="]
+        .iter()
+        .any(|op| rest.starts_with(op))
+}
+
+fn line_range(text: Option<&str>, line: u32) -> Range {
+    let length = text
+        .and_then(|text| text.lines().nth(line as usize))
+        .map_or(0, |l| l.encode_utf16().count() as u32);
+    Range::new(Position::new(line, 0), Position::new(line, length))
+}
+
+fn file_url(path: &Path) -> Option<Url> {
+    Url::from_file_path(path).ok()
+}
+
+impl Server {
+    fn text(&self, path: &Path) -> Option<String> {
+        self.documents
+            .get(path)
+            .cloned()
+            .or_else(|| fs::read_to_string(path).ok())
+    }
+
+    fn patch_options(&self) -> AdvancedPatchOptions {
+        let mut options = AdvancedPatchOptions::new();
+        for path in &self.config.include_paths {
+            options = options.option(PatchOption::Include(path.to_string_lossy().into_owned()));
+        }
+        for (name, value) in &self.config.defines {
+            options = options.option(PatchOption::Define(name.clone(), value.clone()));
+        }
+        for (path, text) in &self.documents {
+            options = options.option(PatchOption::MemoryFile(
+                path.to_string_lossy().into_owned(),
+                text.clone().into(),
+            ));
+        }
+        options
+    }
+
+    /// Assembles the patch and publishes the diagnostics, `changed` is used as the entry when none is configured.
+    fn assemble(&mut self, changed: &Path) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let entry = self
+            .config
+            .entry
+            .clone()
+            .unwrap_or_else(|| changed.to_path_buf());
+        let entry_name = entry.to_string_lossy().into_owned();
+        let options = self.patch_options();
+
+        let mut data = self
+            .config
+            .rom
+            .as_ref()
+            .and_then(|rom| fs::read(rom).ok())
+            .unwrap_or_default();
+        let length = data.len();
+        data.resize((asar::max_rom_size() as usize).max(length), 0);
+
+        let files = deps::scan(&entry, &options)
+            .map(|graph| graph.files().iter().map(|f| f.path.clone()).collect())
+            .unwrap_or_default();
+        let (result, warnings, labels, defines) = asar::with_asar_lock(|| {
+            let result = asar::patching::patch_ex(RomData::new(data, length), entry_name, options);
+            (
+                result,
+                asar::patching::warnings(),
+                asar::patching::labels(),
+                asar::patching::defines(),
+            )
+        });
+
+        let messages = match result {
+            PatchResult::Success(_, warnings) => {
+                self.symbols = Symbols {
+                    labels,
+                    defines,
+                    files,
+                };
+                warnings
+                    .into_iter()
+                    .map(|w| (w, DiagnosticSeverity::WARNING))
+                    .collect()
+            }
+            PatchResult::Failure(errors) => {
+                // warnings denied by the warning policy are already reported as errors
+                let warnings = warnings
+                    .into_iter()
+                    .filter(|w| !errors.iter().any(|e| e.fullerrdata == w.fullerrdata))
+                    .map(|w| (w, DiagnosticSeverity::WARNING))
+                    .collect::<Vec<_>>();
+                errors
+                    .into_iter()
+                    .map(|e| (e, DiagnosticSeverity::ERROR))
+                    .chain(warnings)
+                    .collect()
+            }
+        };
+        self.publish(&entry, messages)
+    }
+
+    fn publish(
+        &mut self,
+        entry: &Path,
+        messages: Vec<(ErrorData, DiagnosticSeverity)>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let mut diagnostics: HashMap<PathBuf, Vec<Diagnostic>> = HashMap::new();
+        for (message, severity) in messages {
+            // errors without a location (e.g. ROM size errors) are shown at the start of the entry file
+            let (path, line) = if message.filename.is_empty() || message.line < 1 {
+                (entry.to_path_buf(), 0)
+            } else {
+                let path = deps::normalize(&self.config.root.join(&message.filename));
+                (path, message.line as u32 - 1)
+            };
+            let range = line_range(self.text(&path).as_deref(), line);
+            // the name of the id, e.g. `Eunknown_command`, or the bare errid if it isn't known
+            let code = message
+                .error_id()
+                .map(|id| id.to_string())
+                .or_else(|| message.warning_id().map(|id| id.to_string()))
+                .map(NumberOrString::String)
+                .or_else(|| (message.errid > 0).then_some(NumberOrString::Number(message.errid)));
+            diagnostics.entry(path).or_default().push(Diagnostic {
+                range,
+                severity: Some(severity),
+                code,
+                source: Some("asar".into()),
+                message: message.rawerrdata,
+                ..Default::default()
+            });
+        }
+
+        let previous = std::mem::take(&mut self.diagnosed);
+        for path in previous.difference(&diagnostics.keys().cloned().collect()) {
+            self.send_diagnostics(path, Vec::new())?;
+        }
+        for (path, diagnostics) in diagnostics {
+            self.send_diagnostics(&path, diagnostics)?;
+            self.diagnosed.insert(path);
+        }
+        Ok(())
+    }
+
+    fn send_diagnostics(
+        &self,
+        path: &Path,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let Some(uri) = file_url(path) else {
+            return Ok(());
+        };
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        };
+        let notification = Notification::new(PublishDiagnostics::METHOD.into(), params);
+        self.connection.sender.send(notification.into())?;
+        Ok(())
+    }
+
+    /// Resolves the word under the cursor to the name of a label or a define (with its `!`).
+    fn symbol_at(&self, uri: &Url, position: Position) -> Option<String> {
+        let path = uri.to_file_path().ok()?;
+        let text = self.text(&path)?;
+        let word = word_at(&text, position)?;
+        if word.starts_with('!') {
+            return Some(word.to_string());
+        }
+        let name = word.trim_start_matches('.');
+        if word.starts_with('.') {
+            let parent = parent_label(&text, position.line as usize)?;
+            return Some(format!("{}_{}", parent, name));
+        }
+        Some(name.to_string())
+    }
+
+    fn find_definition(&self, symbol: &str) -> Option<Location> {
+        for path in &self.symbols.files {
+            let Some(text) = self.text(path) else {
+                continue;
+            };
+            for (index, line) in text.lines().enumerate() {
+                let found = match symbol.strip_prefix('!') {
+                    Some(define) => defines_define(line, define),
+                    None => {
+                        defines_label(line, symbol) || defines_sublabel(&text, index, line, symbol)
+                    }
+                };
+                if found {
+                    return Some(Location::new(
+                        file_url(path)?,
+                        line_range(Some(&text), index as u32),
+                    ));
+                }
+            }
+        }
+        None
+    }
+
+    fn hover(&self, symbol: &str) -> Option<Hover> {
+        let value = match symbol.strip_prefix('!') {
+            Some(name) => {
+                let define = self.symbols.defines.iter().find(|d| d.name == name)?;
+                format!("```asar\n!{} = {}\n```", define.name, define.contents)
+            }
+            None => {
+                let label = self.symbols.labels.iter().find(|l| l.name == symbol)?;
+                format!("```asar\n{} = ${:06X}\n```", label.name, label.location)
+            }
+        };
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: None,
+        })
+    }
+
+    fn completions(&self) -> Vec<CompletionItem> {
+        let labels = self.symbols.labels.iter().map(|label| CompletionItem {
+            label: label.name.clone(),
+            kind: Some(CompletionItemKind::CONSTANT),
+            detail: Some(format!("${:06X}", label.location)),
+            ..Default::default()
+        });
+        let defines = self.symbols.defines.iter().map(|define| CompletionItem {
+            label: format!("!{}", define.name),
+            kind: Some(CompletionItemKind::VARIABLE),
+            detail: Some(define.contents.clone()),
+            // the `!` is usually already typed, since it triggers the completion
+            filter_text: Some(define.name.clone()),
+            insert_text: Some(define.name.clone()),
+            ..Default::default()
+        });
+        labels.chain(defines).collect()
+    }
+
+    fn handle_request(&self, request: Request) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let id = request.id.clone();
+        let response = self.respond(request).unwrap_or_else(|err| {
+            Response::new_err(id, ErrorCode::InvalidParams as i32, err.to_string())
+        });
+        self.connection.sender.send(response.into())?;
+        Ok(())
+    }
+
+    /// Returns the response to `request`, or an error if its params are malformed.
+    fn respond(&self, request: Request) -> Result<Response, serde_json::Error> {
+        let response = match request.method.as_str() {
+            GotoDefinition::METHOD => {
+                let params: GotoDefinitionParams = serde_json::from_value(request.params)?;
+                let position = params.text_document_position_params;
+                let location = self
+                    .symbol_at(&position.text_document.uri, position.position)
+                    .and_then(|symbol| self.find_definition(&symbol))
+                    .map(GotoDefinitionResponse::Scalar);
+                Response::new_ok(request.id, location)
+            }
+            HoverRequest::METHOD => {
+                let params: HoverParams = serde_json::from_value(request.params)?;
+                let position = params.text_document_position_params;
+                let hover = self
+                    .symbol_at(&position.text_document.uri, position.position)
+                    .and_then(|symbol| self.hover(&symbol));
+                Response::new_ok(request.id, hover)
+            }
+            Completion::METHOD => {
+                let _: CompletionParams = serde_json::from_value(request.params)?;
+                Response::new_ok(request.id, CompletionResponse::Array(self.completions()))
+            }
+            method => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unsupported request: {}", method),
+            ),
+        };
+        Ok(response)
+    }
+
+    fn handle_notification(
+        &mut self,
+        notification: Notification,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let method = notification.method.clone();
+        match self.update_document(notification) {
+            Ok(Some(changed)) => self.assemble(&changed),
+            Ok(None) => Ok(()),
+            // a malformed notification can't be answered, so it is only logged
+            Err(err) => {
+                eprintln!(
+                    "asar-lsp: ignoring invalid {} notification: {}",
+                    method, err
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Applies a document notification, returning the path of the document if the patch must be assembled again.
+    fn update_document(
+        &mut self,
+        notification: Notification,
+    ) -> Result<Option<PathBuf>, serde_json::Error> {
+        let changed = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let Ok(path) = params.text_document.uri.to_file_path() else {
+                    return Ok(None);
+                };
+                self.documents
+                    .insert(path.clone(), params.text_document.text);
+                path
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let Ok(path) = params.text_document.uri.to_file_path() else {
+                    return Ok(None);
+                };
+                // the server asks for full document sync, so the last change holds the whole text
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents.insert(path.clone(), change.text);
+                }
+                path
+            }
+            DidSaveTextDocument::METHOD => {
+                let params: lsp_types::DidSaveTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let Ok(path) = params.text_document.uri.to_file_path() else {
+                    return Ok(None);
+                };
+                path
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let Ok(path) = params.text_document.uri.to_file_path() else {
+                    return Ok(None);
+                };
+                // the file on disk is used from now on
+                self.documents.remove(&path);
+                path
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(changed))
+    }
+
+    fn run(mut self) -> Result<(), Box<dyn Error + Sync + Send>> {
+        for message in &self.connection.receiver.clone() {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    self.handle_request(request)?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["!".into()]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let params = connection.initialize(serde_json::to_value(capabilities)?)?;
+    let params: InitializeParams = serde_json::from_value(params)?;
+
+    let server = Server {
+        connection,
+        config: Config::from_params(&params),
+        documents: HashMap::new(),
+        symbols: Symbols::default(),
+        diagnosed: HashSet::new(),
+    };
+    server.run()?;
+    io_threads.join()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = "\
+!speed = 2
+main:
+    lda #!speed
+.loop:
+    bra .loop
+other: rts
+";
+
+    /// Returns a server with the document `path` open, and the client end of its connection.
+    fn server(path: &Path, text: &str) -> (Server, Connection) {
+        let (connection, client) = Connection::memory();
+        let server = Server {
+            connection,
+            config: Config::default(),
+            documents: HashMap::from([(path.to_path_buf(), text.to_string())]),
+            symbols: Symbols::default(),
+            diagnosed: HashSet::new(),
+        };
+        (server, client)
+    }
+
+    #[test]
+    fn test_byte_offset() {
+        assert_eq!(byte_offset("lda #$01", 0), 0);
+        assert_eq!(byte_offset("lda #$01", 4), 4);
+        assert_eq!(byte_offset("lda #$01", 100), 8);
+        // é is 2 bytes and 1 UTF-16 unit, 𝄞 is 4 bytes and 2 UTF-16 units
+        assert_eq!(byte_offset("é: rts", 1), 2);
+        assert_eq!(byte_offset("𝄞: rts", 2), 4);
+        assert_eq!(byte_offset("𝄞: rts", 3), 5);
+    }
+
+    #[test]
+    fn test_word_at() {
+        assert_eq!(word_at(SOURCE, Position::new(2, 10)), Some("!speed"));
+        assert_eq!(word_at(SOURCE, Position::new(2, 4)), Some("lda"));
+        assert_eq!(word_at(SOURCE, Position::new(4, 9)), Some(".loop"));
+        assert_eq!(word_at(SOURCE, Position::new(1, 0)), Some("main"));
+        // the cursor right after a word still selects it
+        assert_eq!(word_at(SOURCE, Position::new(1, 4)), Some("main"));
+        assert_eq!(word_at(SOURCE, Position::new(2, 0)), None);
+        assert_eq!(word_at(SOURCE, Position::new(100, 0)), None);
+        assert_eq!(word_at("é lda", Position::new(0, 3)), Some("lda"));
+    }
+
+    #[test]
+    fn test_parent_label() {
+        assert_eq!(parent_label(SOURCE, 0), None);
+        assert_eq!(parent_label(SOURCE, 1), Some("main"));
+        assert_eq!(parent_label(SOURCE, 4), Some("main"));
+        assert_eq!(parent_label(SOURCE, 5), Some("other"));
+    }
+
+    #[test]
+    fn test_symbol_at() {
+        let path = std::env::temp_dir().join("asar_lsp_test").join("main.asm");
+        let uri = file_url(&path).unwrap();
+        let (server, _client) = server(&path, SOURCE);
+
+        let symbol = |line, character| server.symbol_at(&uri, Position::new(line, character));
+        assert_eq!(symbol(2, 10).as_deref(), Some("!speed"));
+        assert_eq!(symbol(4, 9).as_deref(), Some("main_loop"));
+        assert_eq!(symbol(5, 2).as_deref(), Some("other"));
+        assert_eq!(symbol(2, 0), None);
+
+        let mut server = server;
+        server.symbols.files = vec![path.clone()];
+        let line = |symbol| server.find_definition(symbol).map(|l| l.range.start.line);
+        assert_eq!(line("!speed"), Some(0));
+        assert_eq!(line("main"), Some(1));
+        assert_eq!(line("main_loop"), Some(3));
+        assert_eq!(line("other"), Some(5));
+        assert_eq!(line("missing"), None);
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        let path = std::env::temp_dir().join("asar_lsp_test").join("main.asm");
+        let (server, client) = server(&path, SOURCE);
+
+        let request = |id: i32, method: &str| {
+            Request::new(
+                id.into(),
+                method.into(),
+                serde_json::json!({ "position": 1 }),
+            )
+        };
+        assert!(server.respond(request(1, GotoDefinition::METHOD)).is_err());
+
+        for (id, method) in [
+            (2, GotoDefinition::METHOD),
+            (3, HoverRequest::METHOD),
+            (4, Completion::METHOD),
+        ] {
+            server.handle_request(request(id, method)).unwrap();
+            match client.receiver.try_recv() {
+                Ok(Message::Response(response)) => {
+                    assert_eq!(response.id, id.into());
+                    assert!(response.result.is_none());
+                    let error = response.error.unwrap();
+                    assert_eq!(error.code, ErrorCode::InvalidParams as i32);
+                }
+                message => panic!("expected an error response, got {:?}", message),
+            }
+        }
+    }
+
+    #[test]
+    fn test_malformed_notification() {
+        let path = std::env::temp_dir().join("asar_lsp_test").join("main.asm");
+        let (mut server, client) = server(&path, SOURCE);
+
+        for method in [
+            DidOpenTextDocument::METHOD,
+            DidChangeTextDocument::METHOD,
+            DidSaveTextDocument::METHOD,
+            DidCloseTextDocument::METHOD,
+        ] {
+            let notification =
+                || Notification::new(method.into(), serde_json::json!({ "text": 1 }));
+            assert!(server.update_document(notification()).is_err());
+            server.handle_notification(notification()).unwrap();
+        }
+        assert_eq!(
+            server.documents.get(&path).map(String::as_str),
+            Some(SOURCE)
+        );
+        assert!(server.diagnosed.is_empty());
+        assert!(client.receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,593 @@
+//! Command-line interface compatible with the `asar` executable, built on top of [`asar_snes::patching::patch_ex`].
+//!
+//! Usage: `asar-rs [options] asm_file [rom_file]`, run `asar-rs --help` for the list of options.
+//!
+//! The messages and the exit codes match the ones of Asar: 0 when the patch was applied, 1 otherwise.
+use std::{
+    fs,
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use asar_snes as asar;
+use asar_snes::{
+    AdvancedPatchOptions, ErrorData, ErrorId, PatchOption, PatchResult, RomData, SymbolType,
+    Version,
+};
+
+const USAGE: &str = "[options] asm_file [rom_file]
+
+Supported options:
+
+ --version               Display version information.
+
+ --explain <error>       Display a detailed explanation of an error, e.g. --explain Eunknown_command.
+
+ -v, --verbose           Enable verbose mode.
+
+ --symbols=<none/wla/nocash>
+                         Specifies the format of the symbols output file. (Default is none for no symbols file)
+
+ --symbols-path=<filename>
+                         Override the default path to the symbols output file. The default is the ROM's base name with an
+                         extension of '.sym'.
+
+ --no-title-check
+                         Accepted for compatibility, asar-rs never checks the ROM title.
+
+ --pause-mode=<never/on-error/on-warning/always>
+                         Specify when Asar should pause the application. (Never, on error, on warning or always)
+
+ --fix-checksum=<on/off>
+                         Override Asar's checksum generation, allowing you to manually enable/disable generating a checksum
+
+ -I<path>
+ --include <path>        Add an include search path to Asar.
+
+ -D<def>[=<val>]
+ --define <def>[=<val>]  Add a define (optionally with a value) to Asar.
+
+ -werror                 Treat warnings as errors.
+
+ -w<name>                Enable a specific warning.
+
+ -wno<name>              Disable a specific warning.
+";
+
+/// Size of the copier header some ROM dumps start with.
+const HEADER_SIZE: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum PauseMode {
+    Never,
+    OnError,
+    OnWarning,
+    Always,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Symbols {
+    None,
+    Wla,
+    NoCash,
+}
+
+#[derive(Debug)]
+struct Options {
+    verbose: bool,
+    werror: bool,
+    pause: PauseMode,
+    symbols: Symbols,
+    symbols_path: Option<String>,
+    patch_options: Vec<PatchOption>,
+    asm_file: String,
+    rom_file: Option<String>,
+}
+
+/// The outcome of command-line parsing.
+enum Command {
+    Run(Options),
+    Version,
+    Help,
+    Explain(String),
+}
+
+fn version_string() -> String {
+    format!(
+        "Asar {}, originally developed by Alcaro, maintained by Asar devs.\nSource code: https://github.com/RPGHacker/asar",
+        Version::current().asar_string()
+    )
+}
+
+fn program_name() -> String {
+    std::env::args()
+        .next()
+        .as_deref()
+        .and_then(|arg0| {
+            Path::new(arg0)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| "asar-rs".into())
+}
+
+fn bad_usage() -> ExitCode {
+    print!("usage: {} {}", program_name(), USAGE);
+    ExitCode::from(1)
+}
+
+/// Parses `-D`/`--define` values, `name[=value]`, Asar strips the leading `!` of the name.
+fn parse_define(define: &str) -> PatchOption {
+    let (name, value) = define.split_once('=').unwrap_or((define, ""));
+    let name = name.trim();
+    let name = name.strip_prefix('!').unwrap_or(name);
+    PatchOption::Define(name.into(), value.into())
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Option<Command> {
+    let mut verbose = false;
+    let mut werror = false;
+    let mut pause = PauseMode::Never;
+    let mut symbols = Symbols::None;
+    let mut symbols_path = None;
+    let mut patch_options = Vec::new();
+    let mut positional = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            positional.push(arg);
+            continue;
+        }
+        match arg.as_str() {
+            "--version" => return Some(Command::Version),
+            "--explain" => return Some(Command::Explain(args.next()?)),
+            "-h" | "--help" | "-?" => return Some(Command::Help),
+            "-v" | "--verbose" => verbose = true,
+            "-werror" => werror = true,
+            // the title is never checked, so there is nothing to disable
+            "--no-title-check" => {}
+            "--include" => patch_options.push(PatchOption::Include(args.next()?)),
+            "--define" => patch_options.push(parse_define(&args.next()?)),
+            _ => {
+                if let Some(error) = arg.strip_prefix("--explain=") {
+                    return Some(Command::Explain(error.into()));
+                } else if let Some(mode) = arg.strip_prefix("--pause-mode=") {
+                    pause = match mode {
+                        "never" => PauseMode::Never,
+                        "on-error" => PauseMode::OnError,
+                        "on-warning" => PauseMode::OnWarning,
+                        "always" => PauseMode::Always,
+                        _ => return None,
+                    };
+                } else if let Some(value) = arg.strip_prefix("--fix-checksum=") {
+                    let enabled = match value {
+                        "on" => true,
+                        "off" => false,
+                        _ => return None,
+                    };
+                    patch_options.push(PatchOption::OverrideChecksumGen(true));
+                    patch_options.push(PatchOption::GenerateChecksum(enabled));
+                } else if let Some(format) = arg.strip_prefix("--symbols=") {
+                    symbols = match format {
+                        "none" => Symbols::None,
+                        "wla" => Symbols::Wla,
+                        "nocash" => Symbols::NoCash,
+                        _ => return None,
+                    };
+                } else if let Some(path) = arg.strip_prefix("--symbols-path=") {
+                    symbols_path = Some(path.to_string());
+                } else if let Some(path) = arg.strip_prefix("--include=") {
+                    patch_options.push(PatchOption::Include(path.into()));
+                } else if let Some(define) = arg.strip_prefix("--define=") {
+                    patch_options.push(parse_define(define));
+                } else if let Some(path) = arg.strip_prefix("-I") {
+                    patch_options.push(PatchOption::Include(path.into()));
+                } else if let Some(define) = arg.strip_prefix("-D") {
+                    patch_options.push(parse_define(define));
+                } else if let Some(warning) = arg.strip_prefix("-wno") {
+                    patch_options.push(PatchOption::Warning(warning.into(), false));
+                } else if let Some(warning) = arg.strip_prefix("-w") {
+                    patch_options.push(PatchOption::Warning(warning.into(), true));
+                } else {
+                    return None;
+                }
+            }
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let asm_file = positional.next()?;
+    let rom_file = positional.next();
+    if positional.next().is_some() {
+        return None;
+    }
+    Some(Command::Run(Options {
+        verbose,
+        werror,
+        pause,
+        symbols,
+        symbols_path,
+        patch_options,
+        asm_file,
+        rom_file,
+    }))
+}
+
+/// Asks for a file name on the console, used when the program is started without arguments.
+fn prompt(message: &str) -> Option<String> {
+    print!("{} ", message);
+    io::stdout().flush().ok()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line).ok()?;
+    let line = line.trim().trim_matches('"');
+    (!line.is_empty()).then(|| line.to_string())
+}
+
+fn pause() {
+    println!("Press Enter to continue");
+    let _ = io::stdin().lock().read_line(&mut String::new());
+}
+
+/// Picks the ROM file like Asar does, trying the `.sfc` and `.smc` extensions when the name has none.
+fn rom_path(asm_file: &str, rom_file: Option<String>) -> PathBuf {
+    match rom_file {
+        None => {
+            let base = Path::new(asm_file).with_extension("");
+            let sfc = base.with_extension("sfc");
+            let smc = base.with_extension("smc");
+            if !sfc.exists() && smc.exists() {
+                smc
+            } else {
+                sfc
+            }
+        }
+        Some(rom_file) => {
+            let path = PathBuf::from(&rom_file);
+            if rom_file.contains('.') || path.exists() {
+                return path;
+            }
+            let sfc = PathBuf::from(format!("{}.sfc", rom_file));
+            let smc = PathBuf::from(format!("{}.smc", rom_file));
+            if sfc.exists() {
+                sfc
+            } else if smc.exists() {
+                smc
+            } else {
+                path
+            }
+        }
+    }
+}
+
+fn explain(error: &str) -> ExitCode {
+    match ErrorId::from_name(error) {
+        Some(id) => {
+            println!(
+                "{} (E{}, {})\n\n{}",
+                id,
+                id.errid(),
+                id.category(),
+                id.explain()
+            );
+            ExitCode::SUCCESS
+        }
+        None => {
+            eprintln!("error: '{}' is not a known Asar error", error);
+            ExitCode::from(1)
+        }
+    }
+}
+
+fn print_messages(messages: &[ErrorData]) {
+    let mut stderr = io::stderr().lock();
+    for message in messages {
+        let _ = writeln!(stderr, "{}", message.fullerrdata);
+    }
+}
+
+fn run(options: Options, interactive: bool) -> (ExitCode, bool) {
+    let rom_path = rom_path(&options.asm_file, options.rom_file);
+
+    // Asar creates the ROM when it doesn't exist, it is only written once the patch was applied
+    let rom = if rom_path.exists() {
+        fs::read(&rom_path)
+    } else {
+        Ok(Vec::new())
+    };
+    let rom = match rom {
+        Ok(rom) => rom,
+        Err(_) => {
+            eprintln!("error: (Eopen_rom_failed): Failed to open ROM file.");
+            return (ExitCode::from(1), options.pause >= PauseMode::OnError);
+        }
+    };
+    if rom.len() > asar::max_rom_size() as usize + HEADER_SIZE {
+        eprintln!("error: (Eopen_rom_failed): Failed to open ROM file.");
+        return (ExitCode::from(1), options.pause >= PauseMode::OnError);
+    }
+    let (header, rom) = if rom.len() % 0x8000 == HEADER_SIZE {
+        let (header, rom) = rom.split_at(HEADER_SIZE);
+        (header.to_vec(), rom.to_vec())
+    } else {
+        (Vec::new(), rom)
+    };
+    let length = rom.len();
+    let mut data = rom;
+    data.resize(asar::max_rom_size() as usize, 0);
+
+    let patch_options = AdvancedPatchOptions::from(options.patch_options);
+    let (result, prints, symbols) = asar::with_asar_lock(|| {
+        let result =
+            asar::patching::patch_ex(RomData::new(data, length), options.asm_file, patch_options);
+        let prints = asar::patching::prints();
+        let symbols = match options.symbols {
+            Symbols::None => None,
+            Symbols::Wla => asar::patching::symbols_file(SymbolType::WLA),
+            Symbols::NoCash => asar::patching::symbols_file(SymbolType::NoCash),
+        };
+        (result, prints, symbols)
+    });
+
+    for print in prints {
+        println!("{}", print);
+    }
+
+    let (rom, warnings) = match result {
+        PatchResult::Success(rom, warnings) => (rom, warnings),
+        PatchResult::Failure(errors) => {
+            print_messages(&errors);
+            println!("Errors were detected while assembling the patch. Assembling aborted. Your ROM has not been modified.");
+            return (ExitCode::from(1), options.pause >= PauseMode::OnError);
+        }
+    };
+    print_messages(&warnings);
+    if !warnings.is_empty() && options.werror {
+        println!("One or more warnings was detected with werror on. Assembling aborted. Your ROM has not been modified.");
+        return (ExitCode::from(1), options.pause >= PauseMode::OnError);
+    }
+
+    let mut output = header;
+    output.extend_from_slice(&rom.data[..rom.length]);
+    if fs::write(&rom_path, output).is_err() {
+        eprintln!("error: (Eopen_rom_failed): Failed to open ROM file.");
+        return (ExitCode::from(1), options.pause >= PauseMode::OnError);
+    }
+
+    if let Some(symbols) = symbols {
+        let symbols_path = options
+            .symbols_path
+            .map(PathBuf::from)
+            .unwrap_or_else(|| rom_path.with_extension("sym"));
+        if fs::write(&symbols_path, symbols).is_err() {
+            eprintln!(
+                "error: (Eopen_file_failed): Failed to open file '{}'.",
+                symbols_path.display()
+            );
+            return (ExitCode::from(1), options.pause >= PauseMode::OnError);
+        }
+    }
+
+    if options.verbose || interactive {
+        println!("Assembling completed without problems.");
+    }
+    let should_pause = match options.pause {
+        PauseMode::Always => true,
+        PauseMode::OnWarning => !warnings.is_empty(),
+        _ => false,
+    };
+    (ExitCode::SUCCESS, should_pause)
+}
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    // like Asar, ask for the file names when started without arguments, e.g. from a file manager
+    let interactive = args.is_empty() && io::stdin().is_terminal();
+
+    let options = if interactive {
+        println!("{}", version_string());
+        let Some(asm_file) = prompt("Enter patch name:") else {
+            return bad_usage();
+        };
+        let rom_file = prompt("Enter ROM name:");
+        Options {
+            verbose: true,
+            werror: false,
+            pause: PauseMode::Always,
+            symbols: Symbols::None,
+            symbols_path: None,
+            patch_options: Vec::new(),
+            asm_file,
+            rom_file,
+        }
+    } else {
+        match parse_args(args.into_iter()) {
+            Some(Command::Run(options)) => options,
+            Some(Command::Version) => {
+                println!("{}", version_string());
+                return ExitCode::SUCCESS;
+            }
+            Some(Command::Help) => {
+                println!("{}", version_string());
+                print!("usage: {} {}", program_name(), USAGE);
+                return ExitCode::SUCCESS;
+            }
+            Some(Command::Explain(error)) => return explain(&error),
+            None => return bad_usage(),
+        }
+    };
+    if options.verbose && !interactive {
+        println!("{}", version_string());
+    }
+
+    let (code, should_pause) = run(options, interactive);
+    if should_pause {
+        pause();
+    }
+    code
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Option<Command> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn parse_run(args: &[&str]) -> Options {
+        match parse(args) {
+            Some(Command::Run(options)) => options,
+            _ => panic!("Expected options for {:?}", args),
+        }
+    }
+
+    /// A directory in the temporary directory, emptied first.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_run(&[
+            "-v",
+            "-werror",
+            "--pause-mode=on-warning",
+            "--symbols=wla",
+            "--symbols-path=out.sym",
+            "--fix-checksum=off",
+            "-Iinc",
+            "--include",
+            "lib",
+            "-D!a=1",
+            "--define=b",
+            "-wnoWrelative_path_used",
+            "-wWfeature_deprecated",
+            "main.asm",
+            "rom.sfc",
+        ]);
+        assert!(options.verbose);
+        assert!(options.werror);
+        assert_eq!(options.pause, PauseMode::OnWarning);
+        assert!(matches!(options.symbols, Symbols::Wla));
+        assert_eq!(options.symbols_path.as_deref(), Some("out.sym"));
+        assert_eq!(options.asm_file, "main.asm");
+        assert_eq!(options.rom_file.as_deref(), Some("rom.sfc"));
+        assert_eq!(
+            format!("{:?}", options.patch_options),
+            format!(
+                "{:?}",
+                [
+                    PatchOption::OverrideChecksumGen(true),
+                    PatchOption::GenerateChecksum(false),
+                    PatchOption::Include("inc".into()),
+                    PatchOption::Include("lib".into()),
+                    PatchOption::Define("a".into(), "1".into()),
+                    PatchOption::Define("b".into(), "".into()),
+                    PatchOption::Warning("Wrelative_path_used".into(), false),
+                    PatchOption::Warning("Wfeature_deprecated".into(), true),
+                ]
+            )
+        );
+
+        let options = parse_run(&["main.asm"]);
+        assert!(!options.verbose);
+        assert_eq!(options.pause, PauseMode::Never);
+        assert_eq!(options.rom_file, None);
+
+        assert!(matches!(parse(&["--version"]), Some(Command::Version)));
+        assert!(matches!(parse(&["-?"]), Some(Command::Help)));
+        assert!(
+            matches!(parse(&["--explain", "Eunknown_command"]), Some(Command::Explain(e)) if e == "Eunknown_command")
+        );
+        assert!(
+            matches!(parse(&["--explain=Eunknown_command"]), Some(Command::Explain(e)) if e == "Eunknown_command")
+        );
+        let options = parse_run(&["--no-title-check", "main.asm"]);
+        assert_eq!(options.asm_file, "main.asm");
+        assert!(options.patch_options.is_empty());
+
+        assert!(parse(&[]).is_none());
+        assert!(parse(&["--explain"]).is_none());
+        assert!(parse(&["--include"]).is_none());
+        assert!(parse(&["--pause-mode=sometimes", "main.asm"]).is_none());
+        assert!(parse(&["--symbols=elf", "main.asm"]).is_none());
+        assert!(parse(&["--fix-checksum=maybe", "main.asm"]).is_none());
+        assert!(parse(&["--unknown", "main.asm"]).is_none());
+        assert!(parse(&["main.asm", "rom.sfc", "extra"]).is_none());
+    }
+
+    #[test]
+    fn test_rom_path() {
+        let dir = temp_dir("asar_rs_test_rom_path");
+        let asm = dir.join("main.asm").to_string_lossy().into_owned();
+        let base = dir.join("rom").to_string_lossy().into_owned();
+
+        // nothing exists, the .sfc name is used
+        assert_eq!(rom_path(&asm, None), dir.join("main.sfc"));
+        assert_eq!(rom_path(&asm, Some(base.clone())), dir.join("rom"));
+
+        fs::write(dir.join("main.smc"), []).unwrap();
+        fs::write(dir.join("rom.smc"), []).unwrap();
+        assert_eq!(rom_path(&asm, None), dir.join("main.smc"));
+        assert_eq!(rom_path(&asm, Some(base.clone())), dir.join("rom.smc"));
+
+        fs::write(dir.join("main.sfc"), []).unwrap();
+        fs::write(dir.join("rom.sfc"), []).unwrap();
+        assert_eq!(rom_path(&asm, None), dir.join("main.sfc"));
+        assert_eq!(rom_path(&asm, Some(base.clone())), dir.join("rom.sfc"));
+
+        // names with an extension or existing files are used as they are
+        let named = dir.join("rom.bin").to_string_lossy().into_owned();
+        assert_eq!(rom_path(&asm, Some(named)), dir.join("rom.bin"));
+        fs::write(dir.join("rom"), []).unwrap();
+        assert_eq!(rom_path(&asm, Some(base)), dir.join("rom"));
+    }
+
+    #[test]
+    fn test_explain() {
+        assert_eq!(explain("Eunknown_command"), ExitCode::SUCCESS);
+        assert_eq!(explain("unknown_command"), ExitCode::SUCCESS);
+        assert_eq!(explain("Enot_an_error"), ExitCode::from(1));
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let dir = temp_dir("asar_rs_test_exit_codes");
+        let asm = dir.join("main.asm");
+        let rom = dir.join("main.sfc");
+        let options = |pause| Options {
+            verbose: false,
+            werror: false,
+            pause,
+            symbols: Symbols::None,
+            symbols_path: None,
+            patch_options: Vec::new(),
+            asm_file: asm.to_string_lossy().into_owned(),
+            rom_file: None,
+        };
+
+        // the ROM can't be read
+        fs::create_dir(&rom).unwrap();
+        let (code, should_pause) = run(options(PauseMode::OnError), false);
+        assert_eq!(code, ExitCode::from(1));
+        assert!(should_pause);
+        fs::remove_dir(&rom).unwrap();
+
+        // a failed patch doesn't create the ROM
+        fs::write(&asm, "org $008000\nunknown_command\n").unwrap();
+        let (code, should_pause) = run(options(PauseMode::Never), false);
+        assert_eq!(code, ExitCode::from(1));
+        assert!(!should_pause);
+        assert!(!rom.exists());
+
+        fs::write(&asm, "org $008000\nlda #$01\n").unwrap();
+        let (code, should_pause) = run(options(PauseMode::Always), false);
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert!(should_pause);
+        assert_eq!(fs::read(&rom).unwrap()[0..2], [0xA9, 0x01]);
+    }
+}
@@ -0,0 +1,14 @@
+//! Helper process of [`asar_snes::worker::WorkerPool`], running the patch operations it receives on stdin.
+//!
+//! It is not meant to be run by hand, see the [`asar_snes::worker`] module for the protocol.
+use std::{io, process::ExitCode};
+
+fn main() -> ExitCode {
+    match asar_snes::worker::serve(io::stdin().lock(), io::stdout().lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("asar-worker: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
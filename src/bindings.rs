@@ -0,0 +1,118 @@
+/* Bindings for src/asar/src/asar-dll-bindings/c/asar.h of Asar 1.91, as generated by rust-bindgen 0.69.4 with the
+ * options of build.rs. Regenerate them with the `bindgen` feature and copy $OUT_DIR/bindings.rs here when the header
+ * changes. */
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct errordata {
+    pub fullerrdata: *const ::std::os::raw::c_char,
+    pub rawerrdata: *const ::std::os::raw::c_char,
+    pub block: *const ::std::os::raw::c_char,
+    pub filename: *const ::std::os::raw::c_char,
+    pub line: ::std::os::raw::c_int,
+    pub callerfilename: *const ::std::os::raw::c_char,
+    pub callerline: ::std::os::raw::c_int,
+    pub errid: ::std::os::raw::c_int,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct labeldata {
+    pub name: *const ::std::os::raw::c_char,
+    pub location: ::std::os::raw::c_int,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct definedata {
+    pub name: *const ::std::os::raw::c_char,
+    pub contents: *const ::std::os::raw::c_char,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct writtenblockdata {
+    pub pcoffset: ::std::os::raw::c_int,
+    pub snesoffset: ::std::os::raw::c_int,
+    pub numbytes: ::std::os::raw::c_int,
+}
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum mappertype {
+    invalid_mapper = 0,
+    lorom = 1,
+    hirom = 2,
+    sa1rom = 3,
+    bigsa1rom = 4,
+    sfxrom = 5,
+    exlorom = 6,
+    exhirom = 7,
+    norom = 8,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct warnsetting {
+    pub warnid: *const ::std::os::raw::c_char,
+    pub enabled: bool,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct memoryfile {
+    pub path: *const ::std::os::raw::c_char,
+    pub buffer: *const ::std::os::raw::c_void,
+    pub length: usize,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct patchparams {
+    pub structsize: ::std::os::raw::c_int,
+    pub patchloc: *const ::std::os::raw::c_char,
+    pub romdata: *mut ::std::os::raw::c_char,
+    pub buflen: ::std::os::raw::c_int,
+    pub romlen: *mut ::std::os::raw::c_int,
+    pub includepaths: *mut *const ::std::os::raw::c_char,
+    pub numincludepaths: ::std::os::raw::c_int,
+    pub should_reset: bool,
+    pub additional_defines: *const definedata,
+    pub additional_define_count: ::std::os::raw::c_int,
+    pub stdincludesfile: *const ::std::os::raw::c_char,
+    pub stddefinesfile: *const ::std::os::raw::c_char,
+    pub warning_settings: *const warnsetting,
+    pub warning_setting_count: ::std::os::raw::c_int,
+    pub memory_files: *const memoryfile,
+    pub memory_file_count: ::std::os::raw::c_int,
+    pub override_checksum_gen: bool,
+    pub generate_checksum: bool,
+}
+extern "C" {
+    pub fn asar_version() -> ::std::os::raw::c_int;
+    pub fn asar_apiversion() -> ::std::os::raw::c_int;
+    pub fn asar_reset() -> bool;
+    pub fn asar_patch(
+        patchloc: *const ::std::os::raw::c_char,
+        romdata: *mut ::std::os::raw::c_char,
+        buflen: ::std::os::raw::c_int,
+        romlen: *mut ::std::os::raw::c_int,
+    ) -> bool;
+    pub fn asar_patch_ex(params: *const patchparams) -> bool;
+    pub fn asar_maxromsize() -> ::std::os::raw::c_int;
+    pub fn asar_geterrors(count: *mut ::std::os::raw::c_int) -> *const errordata;
+    pub fn asar_getwarnings(count: *mut ::std::os::raw::c_int) -> *const errordata;
+    pub fn asar_getprints(
+        count: *mut ::std::os::raw::c_int,
+    ) -> *const *const ::std::os::raw::c_char;
+    pub fn asar_getalllabels(count: *mut ::std::os::raw::c_int) -> *const labeldata;
+    pub fn asar_getlabelval(name: *const ::std::os::raw::c_char) -> ::std::os::raw::c_int;
+    pub fn asar_getdefine(name: *const ::std::os::raw::c_char) -> *const ::std::os::raw::c_char;
+    pub fn asar_getalldefines(count: *mut ::std::os::raw::c_int) -> *const definedata;
+    pub fn asar_resolvedefines(
+        data: *const ::std::os::raw::c_char,
+        learnnew: bool,
+    ) -> *const ::std::os::raw::c_char;
+    pub fn asar_math(
+        math: *const ::std::os::raw::c_char,
+        error: *mut *const ::std::os::raw::c_char,
+    ) -> f64;
+    pub fn asar_getwrittenblocks(count: *mut ::std::os::raw::c_int) -> *const writtenblockdata;
+    pub fn asar_getmapper() -> mappertype;
+    pub fn asar_getsymbolsfile(
+        format: *const ::std::os::raw::c_char,
+    ) -> *const ::std::os::raw::c_char;
+}
@@ -0,0 +1,268 @@
+/* Bindings for src/asar/src/asar-dll-bindings/c/asar.h of Asar 1.91, as generated by rust-bindgen 0.69.4 with the
+ * options of build.rs and the `dynamic` feature. Regenerate them with the `bindgen` and `dynamic` features and copy
+ * $OUT_DIR/bindings.rs here when the header changes. */
+
+extern crate libloading;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct errordata {
+    pub fullerrdata: *const ::std::os::raw::c_char,
+    pub rawerrdata: *const ::std::os::raw::c_char,
+    pub block: *const ::std::os::raw::c_char,
+    pub filename: *const ::std::os::raw::c_char,
+    pub line: ::std::os::raw::c_int,
+    pub callerfilename: *const ::std::os::raw::c_char,
+    pub callerline: ::std::os::raw::c_int,
+    pub errid: ::std::os::raw::c_int,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct labeldata {
+    pub name: *const ::std::os::raw::c_char,
+    pub location: ::std::os::raw::c_int,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct definedata {
+    pub name: *const ::std::os::raw::c_char,
+    pub contents: *const ::std::os::raw::c_char,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct writtenblockdata {
+    pub pcoffset: ::std::os::raw::c_int,
+    pub snesoffset: ::std::os::raw::c_int,
+    pub numbytes: ::std::os::raw::c_int,
+}
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum mappertype {
+    invalid_mapper = 0,
+    lorom = 1,
+    hirom = 2,
+    sa1rom = 3,
+    bigsa1rom = 4,
+    sfxrom = 5,
+    exlorom = 6,
+    exhirom = 7,
+    norom = 8,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct warnsetting {
+    pub warnid: *const ::std::os::raw::c_char,
+    pub enabled: bool,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct memoryfile {
+    pub path: *const ::std::os::raw::c_char,
+    pub buffer: *const ::std::os::raw::c_void,
+    pub length: usize,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct patchparams {
+    pub structsize: ::std::os::raw::c_int,
+    pub patchloc: *const ::std::os::raw::c_char,
+    pub romdata: *mut ::std::os::raw::c_char,
+    pub buflen: ::std::os::raw::c_int,
+    pub romlen: *mut ::std::os::raw::c_int,
+    pub includepaths: *mut *const ::std::os::raw::c_char,
+    pub numincludepaths: ::std::os::raw::c_int,
+    pub should_reset: bool,
+    pub additional_defines: *const definedata,
+    pub additional_define_count: ::std::os::raw::c_int,
+    pub stdincludesfile: *const ::std::os::raw::c_char,
+    pub stddefinesfile: *const ::std::os::raw::c_char,
+    pub warning_settings: *const warnsetting,
+    pub warning_setting_count: ::std::os::raw::c_int,
+    pub memory_files: *const memoryfile,
+    pub memory_file_count: ::std::os::raw::c_int,
+    pub override_checksum_gen: bool,
+    pub generate_checksum: bool,
+}
+
+pub struct AsarLibrary {
+    __library: ::libloading::Library,
+    pub asar_version: unsafe extern "C" fn() -> ::std::os::raw::c_int,
+    pub asar_apiversion: unsafe extern "C" fn() -> ::std::os::raw::c_int,
+    pub asar_reset: unsafe extern "C" fn() -> bool,
+    pub asar_patch: unsafe extern "C" fn(
+        patchloc: *const ::std::os::raw::c_char,
+        romdata: *mut ::std::os::raw::c_char,
+        buflen: ::std::os::raw::c_int,
+        romlen: *mut ::std::os::raw::c_int,
+    ) -> bool,
+    pub asar_patch_ex: unsafe extern "C" fn(params: *const patchparams) -> bool,
+    pub asar_maxromsize: unsafe extern "C" fn() -> ::std::os::raw::c_int,
+    pub asar_geterrors: unsafe extern "C" fn(count: *mut ::std::os::raw::c_int) -> *const errordata,
+    pub asar_getwarnings:
+        unsafe extern "C" fn(count: *mut ::std::os::raw::c_int) -> *const errordata,
+    pub asar_getprints: unsafe extern "C" fn(
+        count: *mut ::std::os::raw::c_int,
+    ) -> *const *const ::std::os::raw::c_char,
+    pub asar_getalllabels:
+        unsafe extern "C" fn(count: *mut ::std::os::raw::c_int) -> *const labeldata,
+    pub asar_getlabelval:
+        unsafe extern "C" fn(name: *const ::std::os::raw::c_char) -> ::std::os::raw::c_int,
+    pub asar_getdefine:
+        unsafe extern "C" fn(name: *const ::std::os::raw::c_char) -> *const ::std::os::raw::c_char,
+    pub asar_getalldefines:
+        unsafe extern "C" fn(count: *mut ::std::os::raw::c_int) -> *const definedata,
+    pub asar_resolvedefines: unsafe extern "C" fn(
+        data: *const ::std::os::raw::c_char,
+        learnnew: bool,
+    ) -> *const ::std::os::raw::c_char,
+    pub asar_math: unsafe extern "C" fn(
+        math: *const ::std::os::raw::c_char,
+        error: *mut *const ::std::os::raw::c_char,
+    ) -> f64,
+    pub asar_getwrittenblocks:
+        unsafe extern "C" fn(count: *mut ::std::os::raw::c_int) -> *const writtenblockdata,
+    pub asar_getmapper: unsafe extern "C" fn() -> mappertype,
+    pub asar_getsymbolsfile: unsafe extern "C" fn(
+        format: *const ::std::os::raw::c_char,
+    ) -> *const ::std::os::raw::c_char,
+}
+impl AsarLibrary {
+    pub unsafe fn new<P>(path: P) -> Result<Self, ::libloading::Error>
+    where
+        P: AsRef<::std::ffi::OsStr>,
+    {
+        let library = ::libloading::Library::new(path)?;
+        Self::from_library(library)
+    }
+    pub unsafe fn from_library<L>(library: L) -> Result<Self, ::libloading::Error>
+    where
+        L: Into<::libloading::Library>,
+    {
+        let __library = library.into();
+        let asar_version = __library.get(b"asar_version\0").map(|sym| *sym)?;
+        let asar_apiversion = __library.get(b"asar_apiversion\0").map(|sym| *sym)?;
+        let asar_reset = __library.get(b"asar_reset\0").map(|sym| *sym)?;
+        let asar_patch = __library.get(b"asar_patch\0").map(|sym| *sym)?;
+        let asar_patch_ex = __library.get(b"asar_patch_ex\0").map(|sym| *sym)?;
+        let asar_maxromsize = __library.get(b"asar_maxromsize\0").map(|sym| *sym)?;
+        let asar_geterrors = __library.get(b"asar_geterrors\0").map(|sym| *sym)?;
+        let asar_getwarnings = __library.get(b"asar_getwarnings\0").map(|sym| *sym)?;
+        let asar_getprints = __library.get(b"asar_getprints\0").map(|sym| *sym)?;
+        let asar_getalllabels = __library.get(b"asar_getalllabels\0").map(|sym| *sym)?;
+        let asar_getlabelval = __library.get(b"asar_getlabelval\0").map(|sym| *sym)?;
+        let asar_getdefine = __library.get(b"asar_getdefine\0").map(|sym| *sym)?;
+        let asar_getalldefines = __library.get(b"asar_getalldefines\0").map(|sym| *sym)?;
+        let asar_resolvedefines = __library.get(b"asar_resolvedefines\0").map(|sym| *sym)?;
+        let asar_math = __library.get(b"asar_math\0").map(|sym| *sym)?;
+        let asar_getwrittenblocks = __library.get(b"asar_getwrittenblocks\0").map(|sym| *sym)?;
+        let asar_getmapper = __library.get(b"asar_getmapper\0").map(|sym| *sym)?;
+        let asar_getsymbolsfile = __library.get(b"asar_getsymbolsfile\0").map(|sym| *sym)?;
+        Ok(AsarLibrary {
+            __library,
+            asar_version,
+            asar_apiversion,
+            asar_reset,
+            asar_patch,
+            asar_patch_ex,
+            asar_maxromsize,
+            asar_geterrors,
+            asar_getwarnings,
+            asar_getprints,
+            asar_getalllabels,
+            asar_getlabelval,
+            asar_getdefine,
+            asar_getalldefines,
+            asar_resolvedefines,
+            asar_math,
+            asar_getwrittenblocks,
+            asar_getmapper,
+            asar_getsymbolsfile,
+        })
+    }
+    pub unsafe fn asar_version(&self) -> ::std::os::raw::c_int {
+        (self.asar_version)()
+    }
+    pub unsafe fn asar_apiversion(&self) -> ::std::os::raw::c_int {
+        (self.asar_apiversion)()
+    }
+    pub unsafe fn asar_reset(&self) -> bool {
+        (self.asar_reset)()
+    }
+    pub unsafe fn asar_patch(
+        &self,
+        patchloc: *const ::std::os::raw::c_char,
+        romdata: *mut ::std::os::raw::c_char,
+        buflen: ::std::os::raw::c_int,
+        romlen: *mut ::std::os::raw::c_int,
+    ) -> bool {
+        (self.asar_patch)(patchloc, romdata, buflen, romlen)
+    }
+    pub unsafe fn asar_patch_ex(&self, params: *const patchparams) -> bool {
+        (self.asar_patch_ex)(params)
+    }
+    pub unsafe fn asar_maxromsize(&self) -> ::std::os::raw::c_int {
+        (self.asar_maxromsize)()
+    }
+    pub unsafe fn asar_geterrors(&self, count: *mut ::std::os::raw::c_int) -> *const errordata {
+        (self.asar_geterrors)(count)
+    }
+    pub unsafe fn asar_getwarnings(&self, count: *mut ::std::os::raw::c_int) -> *const errordata {
+        (self.asar_getwarnings)(count)
+    }
+    pub unsafe fn asar_getprints(
+        &self,
+        count: *mut ::std::os::raw::c_int,
+    ) -> *const *const ::std::os::raw::c_char {
+        (self.asar_getprints)(count)
+    }
+    pub unsafe fn asar_getalllabels(&self, count: *mut ::std::os::raw::c_int) -> *const labeldata {
+        (self.asar_getalllabels)(count)
+    }
+    pub unsafe fn asar_getlabelval(
+        &self,
+        name: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int {
+        (self.asar_getlabelval)(name)
+    }
+    pub unsafe fn asar_getdefine(
+        &self,
+        name: *const ::std::os::raw::c_char,
+    ) -> *const ::std::os::raw::c_char {
+        (self.asar_getdefine)(name)
+    }
+    pub unsafe fn asar_getalldefines(
+        &self,
+        count: *mut ::std::os::raw::c_int,
+    ) -> *const definedata {
+        (self.asar_getalldefines)(count)
+    }
+    pub unsafe fn asar_resolvedefines(
+        &self,
+        data: *const ::std::os::raw::c_char,
+        learnnew: bool,
+    ) -> *const ::std::os::raw::c_char {
+        (self.asar_resolvedefines)(data, learnnew)
+    }
+    pub unsafe fn asar_math(
+        &self,
+        math: *const ::std::os::raw::c_char,
+        error: *mut *const ::std::os::raw::c_char,
+    ) -> f64 {
+        (self.asar_math)(math, error)
+    }
+    pub unsafe fn asar_getwrittenblocks(
+        &self,
+        count: *mut ::std::os::raw::c_int,
+    ) -> *const writtenblockdata {
+        (self.asar_getwrittenblocks)(count)
+    }
+    pub unsafe fn asar_getmapper(&self) -> mappertype {
+        (self.asar_getmapper)()
+    }
+    pub unsafe fn asar_getsymbolsfile(
+        &self,
+        format: *const ::std::os::raw::c_char,
+    ) -> *const ::std::os::raw::c_char {
+        (self.asar_getsymbolsfile)(format)
+    }
+}
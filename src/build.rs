@@ -0,0 +1,374 @@
+//! Helpers to assemble patches from a `build.rs` script.
+//!
+//! Every patch is assembled into `OUT_DIR`, producing a `<name>.bin` file with the resulting ROM data
+//! and a `<name>_labels.rs` file with a constant for each label, and the appropriate `cargo:rerun-if-changed`
+//! lines are emitted for the entry file, the include paths and every file included with `incsrc`/`incbin`.
+//!
+//! e.g. in `build.rs`:
+//! ```rust,no_run
+//! asar_snes::build::Build::new()
+//!     .include("asm/include")
+//!     .define("DEBUG", "1")
+//!     .patch("asm/main.asm")
+//!     .compile();
+//! ```
+//! and then in the crate:
+//! ```rust,ignore
+//! static CODE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/main.bin"));
+//! mod labels {
+//!     include!(concat!(env!("OUT_DIR"), "/main_labels.rs"));
+//! }
+//! ```
+use std::{
+    collections::HashMap,
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    deps, AdvancedPatchOptions, ErrorData, Label, PatchOption, PatchResult, RomData, WarningData,
+};
+
+/// A single patch to assemble in a [`Build`].
+#[derive(Debug, Clone)]
+pub struct BuildPatch {
+    entry: PathBuf,
+    name: Option<String>,
+    rom: Option<PathBuf>,
+    options: Vec<PatchOption>,
+}
+
+/// Builder to assemble one or more patches from a `build.rs` script.
+///
+/// See the [module documentation](self) for more information.
+#[derive(Debug, Clone)]
+pub struct Build {
+    patches: Vec<BuildPatch>,
+    options: Vec<PatchOption>,
+    includepaths: Vec<PathBuf>,
+    out_dir: Option<PathBuf>,
+    emit_rerun_if_changed: bool,
+}
+
+/// The files produced for a single patch by [`Build::try_compile`].
+#[derive(Debug, Clone)]
+pub struct BuildOutput {
+    pub name: String,
+    pub rom_path: PathBuf,
+    pub labels_path: PathBuf,
+    pub labels: Vec<Label>,
+    pub warnings: Vec<WarningData>,
+}
+
+/// Error returned by [`Build::try_compile`].
+#[derive(Debug)]
+pub enum BuildError {
+    /// Reading or writing a file failed.
+    Io(PathBuf, io::Error),
+    /// Asar failed to assemble the patch.
+    Assembly(PathBuf, Vec<ErrorData>),
+    /// Two labels of the patch map to the same constant in the generated labels file, e.g. `main.loop` and `main_loop`.
+    LabelConflict {
+        path: PathBuf,
+        constant: String,
+        first: String,
+        second: String,
+    },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            BuildError::Assembly(path, errors) => {
+                write!(f, "Failed to assemble {}", path.display())?;
+                for error in errors {
+                    write!(f, "\n{}", error.fullerrdata)?;
+                }
+                Ok(())
+            }
+            BuildError::LabelConflict {
+                path,
+                constant,
+                first,
+                second,
+            } => write!(
+                f,
+                "{}: the labels `{}` and `{}` both map to the constant `{}`",
+                path.display(),
+                first,
+                second,
+                constant
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl BuildPatch {
+    /// Creates a new patch with the entry file provided.
+    ///
+    /// By default the output files are named after the entry file, and the patch is applied to an empty ROM.
+    pub fn new<P: Into<PathBuf>>(entry: P) -> BuildPatch {
+        BuildPatch {
+            entry: entry.into(),
+            name: None,
+            rom: None,
+            options: Vec::new(),
+        }
+    }
+
+    /// Sets the name of the output files, `<name>.bin` and `<name>_labels.rs`.
+    pub fn name<T: Into<String>>(mut self, name: T) -> BuildPatch {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets the ROM the patch is applied to.
+    pub fn rom<P: Into<PathBuf>>(mut self, rom: P) -> BuildPatch {
+        self.rom = Some(rom.into());
+        self
+    }
+
+    /// Adds an option only for this patch, in addition to the ones set on the [`Build`].
+    pub fn option(mut self, option: PatchOption) -> BuildPatch {
+        self.options.push(option);
+        self
+    }
+
+    fn output_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| {
+            self.entry
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "patch".into())
+        })
+    }
+}
+
+impl Build {
+    /// Creates a new Build, with no patches.
+    pub fn new() -> Build {
+        Build {
+            patches: Vec::new(),
+            options: Vec::new(),
+            includepaths: Vec::new(),
+            out_dir: None,
+            emit_rerun_if_changed: true,
+        }
+    }
+
+    /// Adds a patch, with the default settings, see [`BuildPatch::new`].
+    pub fn patch<P: Into<PathBuf>>(self, entry: P) -> Build {
+        self.patch_with(BuildPatch::new(entry))
+    }
+
+    /// Adds a patch.
+    pub fn patch_with(mut self, patch: BuildPatch) -> Build {
+        self.patches.push(patch);
+        self
+    }
+
+    /// Adds an include path to all the patches.
+    pub fn include<P: Into<PathBuf>>(mut self, path: P) -> Build {
+        self.includepaths.push(path.into());
+        self
+    }
+
+    /// Adds a define to all the patches.
+    pub fn define<N: Into<String>, V: Into<String>>(self, name: N, value: V) -> Build {
+        self.option(PatchOption::Define(name.into(), value.into()))
+    }
+
+    /// Adds an option to all the patches.
+    pub fn option(mut self, option: PatchOption) -> Build {
+        self.options.push(option);
+        self
+    }
+
+    /// Sets the output directory, by default it is `OUT_DIR`.
+    pub fn out_dir<P: Into<PathBuf>>(mut self, out_dir: P) -> Build {
+        self.out_dir = Some(out_dir.into());
+        self
+    }
+
+    /// Sets whether `cargo:rerun-if-changed` lines should be printed, by default they are.
+    pub fn emit_rerun_if_changed(mut self, emit: bool) -> Build {
+        self.emit_rerun_if_changed = emit;
+        self
+    }
+
+    /// Assembles all the patches, panicking with Asar's errors if any of them fails.
+    ///
+    /// Warnings are printed as `cargo:warning` lines.
+    pub fn compile(self) -> Vec<BuildOutput> {
+        match self.try_compile() {
+            Ok(outputs) => {
+                for warning in outputs.iter().flat_map(|o| o.warnings.iter()) {
+                    println!("cargo:warning={}", warning.fullerrdata);
+                }
+                outputs
+            }
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Assembles all the patches, returning the produced files.
+    ///
+    /// remarks: This function uses the global lock.
+    pub fn try_compile(self) -> Result<Vec<BuildOutput>, BuildError> {
+        let out_dir = match &self.out_dir {
+            Some(out_dir) => out_dir.clone(),
+            None => PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is not set")),
+        };
+        let includepaths = self
+            .includepaths
+            .iter()
+            .map(|p| absolute(p))
+            .collect::<Vec<_>>();
+
+        if self.emit_rerun_if_changed {
+            for path in &includepaths {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+
+        self.patches
+            .iter()
+            .map(|patch| self.compile_patch(patch, &includepaths, &out_dir))
+            .collect()
+    }
+
+    fn compile_patch(
+        &self,
+        patch: &BuildPatch,
+        includepaths: &[PathBuf],
+        out_dir: &Path,
+    ) -> Result<BuildOutput, BuildError> {
+        let entry = absolute(&patch.entry);
+
+        let mut data = vec![0; crate::max_rom_size() as usize];
+        let mut length = 0;
+        if let Some(rom) = &patch.rom {
+            let rom = absolute(rom);
+            if self.emit_rerun_if_changed {
+                println!("cargo:rerun-if-changed={}", rom.display());
+            }
+            let contents = fs::read(&rom).map_err(|err| BuildError::Io(rom.clone(), err))?;
+            length = contents.len().min(data.len());
+            data[..length].copy_from_slice(&contents[..length]);
+        }
+
+        let options = AdvancedPatchOptions::new()
+            .options(
+                includepaths
+                    .iter()
+                    .map(|p| PatchOption::Include(p.to_string_lossy().into_owned()))
+                    .collect(),
+            )
+            .options(self.options.clone())
+            .options(patch.options.clone());
+
+        if self.emit_rerun_if_changed {
+            println!("cargo:rerun-if-changed={}", entry.display());
+            if let Ok(graph) = deps::scan(&entry, &options) {
+                for dependency in graph.disk_files().filter(|path| *path != entry) {
+                    println!("cargo:rerun-if-changed={}", dependency.display());
+                }
+            }
+        }
+
+        let (result, labels) = crate::with_asar_lock(|| {
+            let result = crate::patching::patch_ex(
+                RomData::new(data, length),
+                entry.to_string_lossy(),
+                options,
+            );
+            (result, crate::patching::labels())
+        });
+
+        let (romdata, warnings) = match result {
+            PatchResult::Success(romdata, warnings) => (romdata, warnings),
+            PatchResult::Failure(errors) => return Err(BuildError::Assembly(entry, errors)),
+        };
+
+        let name = patch.output_name();
+        let rom_path = out_dir.join(format!("{}.bin", name));
+        let labels_path = out_dir.join(format!("{}_labels.rs", name));
+        let module = labels_module(&entry, &labels)?;
+        fs::write(&rom_path, &romdata.data[..romdata.length])
+            .map_err(|err| BuildError::Io(rom_path.clone(), err))?;
+        fs::write(&labels_path, module).map_err(|err| BuildError::Io(labels_path.clone(), err))?;
+
+        Ok(BuildOutput {
+            name,
+            rom_path,
+            labels_path,
+            labels,
+            warnings,
+        })
+    }
+}
+
+impl Default for Build {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    match env::var_os("CARGO_MANIFEST_DIR") {
+        Some(dir) => PathBuf::from(dir).join(path),
+        None => std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+/// Converts a label name into a valid Rust constant name, e.g. `main_loop` -> `MAIN_LOOP`.
+fn const_name(label: &str) -> String {
+    let mut name = label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Generates the labels file of the patch `entry`, failing if two labels map to the same constant.
+pub(crate) fn labels_module(entry: &Path, labels: &[Label]) -> Result<String, BuildError> {
+    let mut module = String::from("// Generated by asar_snes::build, do not edit.\n\n");
+    let mut seen = HashMap::new();
+    for label in labels {
+        let name = const_name(&label.name);
+        if let Some(first) = seen.insert(name.clone(), &label.name) {
+            return Err(BuildError::LabelConflict {
+                path: entry.to_path_buf(),
+                constant: name,
+                first: first.clone(),
+                second: label.name.clone(),
+            });
+        }
+        module += &format!(
+            "/// `{}`\npub const {}: u32 = 0x{:06X};\n",
+            label.name, name, label.location as u32
+        );
+    }
+    module +=
+        "\n/// All the labels, with their original names.\npub const LABELS: &[(&str, u32)] = &[\n";
+    for label in labels {
+        module += &format!("    ({:?}, 0x{:06X}),\n", label.name, label.location as u32);
+    }
+    module += "];\n";
+    Ok(module)
+}
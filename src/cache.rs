@@ -0,0 +1,368 @@
+//! Incremental patch cache, keyed by a hash of all the inputs of a patch operation.
+//!
+//! The key covers the input ROM, the patch path, every [`AdvancedPatchOptions`] setting (defines, warning settings,
+//! memory files, include paths, ...), the Asar version and the contents of every file the patch depends on,
+//! as found by [`deps::scan`]. When the key matches a stored entry, the stored result is returned without calling Asar.
+//!
+//! Only successful patch operations are stored, and patch operations with [`PatchOption::ShouldReset`](crate::PatchOption::ShouldReset)
+//! set to false always call Asar, since their result depends on the previous patch operation. The same goes for patches
+//! including a file through a define that [`deps::scan`] can't resolve, since the file can't be hashed.
+//!
+//! e.g.
+//! ```rust,no_run
+//! use asar_snes::cache::{CachedPatchResult, PatchCache};
+//! use asar_snes::AdvancedPatchOptions;
+//!
+//! let cache = PatchCache::new("target/asar-cache").unwrap().max_entries(500);
+//! let rom = std::fs::read("base.sfc").unwrap();
+//! match cache.patch(rom.into(), "main.asm", AdvancedPatchOptions::new()) {
+//!     CachedPatchResult::Success(patch) => println!("cached: {}", patch.from_cache),
+//!     CachedPatchResult::Failure(errors) => println!("{:?}", errors),
+//! }
+//! ```
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    codec::{Decoder, Encoder},
+    deps, AdvancedPatchOptions, ErrorData, Label, MemoryFileData, PatchResult, RomData,
+    WarningData, WrittenBlock,
+};
+
+const MAGIC: u32 = u32::from_le_bytes(*b"ASRC");
+const FORMAT_VERSION: u32 = 1;
+const ENTRY_EXTENSION: &str = "entry";
+
+/// The result of a successful patch operation, either stored in the cache or produced by Asar.
+#[derive(Debug, Clone)]
+pub struct CachedPatch {
+    pub romdata: RomData,
+    pub labels: Vec<Label>,
+    pub warnings: Vec<WarningData>,
+    pub written_blocks: Vec<WrittenBlock>,
+    /// Whether the result was read from the cache instead of calling Asar.
+    pub from_cache: bool,
+}
+
+/// The result of [`PatchCache::patch`].
+#[derive(Debug, Clone)]
+pub enum CachedPatchResult {
+    Success(CachedPatch),
+    Failure(Vec<ErrorData>),
+}
+
+/// An on-disk cache of patch results.
+///
+/// See the [module documentation](self) for more information.
+#[derive(Debug, Clone)]
+pub struct PatchCache {
+    dir: PathBuf,
+    max_entries: Option<usize>,
+    max_bytes: Option<u64>,
+}
+
+/// Feeds the values to the hash with length prefixes, so that different inputs can't produce the same stream.
+struct KeyHasher(Sha256);
+
+impl KeyHasher {
+    fn u64(&mut self, value: u64) {
+        self.0.update(value.to_le_bytes());
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.0.update([value as u8]);
+    }
+
+    fn bytes(&mut self, value: &[u8]) {
+        self.u64(value.len() as u64);
+        self.0.update(value);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+
+    fn opt_str(&mut self, value: Option<&str>) {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            self.str(value);
+        }
+    }
+
+    fn finish(self) -> String {
+        self.0
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+impl PatchCache {
+    /// Creates a new cache stored in `dir`, creating the directory if it doesn't exist.
+    ///
+    /// By default the cache is never evicted, see [`PatchCache::max_entries`] and [`PatchCache::max_bytes`].
+    pub fn new<P: Into<PathBuf>>(dir: P) -> io::Result<PatchCache> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(PatchCache {
+            dir,
+            max_entries: None,
+            max_bytes: None,
+        })
+    }
+
+    /// Sets the maximum number of entries, the least recently used entries are evicted when it is exceeded.
+    pub fn max_entries(mut self, max_entries: usize) -> PatchCache {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Sets the maximum total size of the entries in bytes, the least recently used entries are evicted when it is exceeded.
+    pub fn max_bytes(mut self, max_bytes: u64) -> PatchCache {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Returns the directory the cache is stored in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Patches the ROM data like [`patching::patch_ex`](crate::patching::patch_ex), returning the stored result if the inputs didn't change.
+    ///
+    /// Errors while reading or writing the cache are ignored, in that case Asar is called and the result is not stored.
+    ///
+    /// remarks: This function uses the global lock.
+    pub fn patch<T: Into<String>>(
+        &self,
+        rom: RomData,
+        patch: T,
+        options: AdvancedPatchOptions,
+    ) -> CachedPatchResult {
+        let patch = patch.into();
+        let key = if options.should_reset {
+            self.key(&rom, &patch, &options)
+        } else {
+            None
+        };
+        // kept to compute the key again after the patch, see below
+        let inputs = key.as_ref().map(|_| (rom.clone(), options.clone()));
+
+        if let Some(key) = &key {
+            if let Ok(cached) = self.load(key, &rom) {
+                return CachedPatchResult::Success(cached);
+            }
+        }
+
+        let (result, labels, written_blocks) = crate::with_asar_lock(|| {
+            let result = crate::patching::patch_ex(rom, patch.clone(), options);
+            (
+                result,
+                crate::patching::labels(),
+                crate::patching::written_blocks(),
+            )
+        });
+
+        match result {
+            PatchResult::Success(romdata, warnings) => {
+                // a file that was missing when the key was computed but existed when Asar read it was not hashed,
+                // nor was a file modified during the patch, in both cases the key changes and nothing is stored
+                let key = key.filter(|key| {
+                    inputs.is_some_and(|(rom, options)| {
+                        self.key(&rom, &patch, &options).as_ref() == Some(key)
+                    })
+                });
+                let patch = CachedPatch {
+                    romdata,
+                    labels,
+                    warnings,
+                    written_blocks,
+                    from_cache: false,
+                };
+                if let Some(key) = &key {
+                    if self.store(key, &patch).is_ok() {
+                        let _ = self.evict();
+                    }
+                }
+                CachedPatchResult::Success(patch)
+            }
+            PatchResult::Failure(errors) => CachedPatchResult::Failure(errors),
+        }
+    }
+
+    /// Removes all the entries from the cache.
+    pub fn clear(&self) -> io::Result<()> {
+        for (path, _, _) in self.entries()? {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Computes the key of a patch operation, None if one of the dependencies could not be read.
+    fn key(&self, rom: &RomData, patch: &str, options: &AdvancedPatchOptions) -> Option<String> {
+        let mut hasher = KeyHasher(Sha256::new());
+        hasher.u64(FORMAT_VERSION as u64);
+        hasher.u64(crate::version() as u64);
+        hasher.bytes(&rom.data);
+        hasher.u64(rom.length as u64);
+        hasher.str(patch);
+
+        hasher.u64(options.includepaths.len() as u64);
+        for path in &options.includepaths {
+            hasher.str(path);
+        }
+        hasher.u64(options.additional_defines.len() as u64);
+        for define in &options.additional_defines {
+            hasher.str(&define.name);
+            hasher.str(&define.contents);
+        }
+        hasher.u64(options.warning_settings.len() as u64);
+        for setting in &options.warning_settings {
+            hasher.str(&setting.warnid);
+            hasher.bool(setting.enabled);
+        }
+        hasher.u64(options.memory_files.len() as u64);
+        for file in &options.memory_files {
+            hasher.str(&file.filename);
+            match &file.data {
+                MemoryFileData::Binary(data) => hasher.bytes(data),
+                MemoryFileData::Text(text) => hasher.str(text),
+            }
+        }
+        hasher.opt_str(options.stdincludesfile.as_deref());
+        hasher.opt_str(options.stddefinesfile.as_deref());
+        for file in [&options.stdincludesfile, &options.stddefinesfile]
+            .into_iter()
+            .flatten()
+        {
+            hasher.bytes(&fs::read(file).ok()?);
+        }
+        hasher.bool(options.override_checksum_gen);
+        hasher.bool(options.generate_checksum);
+        let policy = &options.warning_policy;
+        hasher.bool(policy.deny_all);
+        for list in [&policy.deny, &policy.allow] {
+            hasher.u64(list.len() as u64);
+            for warning in list {
+                hasher.u64(warning.errid() as u64);
+            }
+        }
+        hasher.u64(policy.allow_paths.len() as u64);
+        for (path, warning) in &policy.allow_paths {
+            hasher.str(&path.to_string_lossy());
+            hasher.u64(warning.map_or(0, |w| w.errid() as u64));
+        }
+
+        // the files found in the std include paths are part of the graph, so they are hashed below
+        let graph = deps::scan(patch, options).ok()?;
+        for file in graph.disk_files() {
+            hasher.str(&file.to_string_lossy());
+            hasher.bytes(&fs::read(file).ok()?);
+        }
+        for include in graph.unresolved() {
+            // the scanner doesn't know the value of the define, so the file read by Asar can't be hashed
+            if include.target.contains('!') {
+                return None;
+            }
+            // a missing file may be created later, which changes the result of the patch
+            hasher.str(&include.target);
+        }
+
+        Some(hasher.finish())
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(key).with_extension(ENTRY_EXTENSION)
+    }
+
+    /// Loads an entry, the ROM data is rebuilt from the input ROM, since Asar never writes past the new ROM length.
+    fn load(&self, key: &str, rom: &RomData) -> io::Result<CachedPatch> {
+        let path = self.entry_path(key);
+        let data = fs::read(&path)?;
+        let mut decoder = Decoder::new(data.as_slice());
+        if decoder.u32()? != MAGIC || decoder.u32()? != FORMAT_VERSION {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        let written = decoder.bytes()?;
+        let labels = decoder.list(Decoder::label)?;
+        let warnings = decoder.list(Decoder::error)?;
+        let written_blocks = decoder.list(Decoder::written_block)?;
+        if written.len() > rom.data.len() {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+
+        // mark the entry as recently used
+        let _ = fs::File::options()
+            .append(true)
+            .open(&path)
+            .and_then(|f| f.set_modified(SystemTime::now()));
+
+        let mut romdata = rom.clone();
+        romdata.data[..written.len()].copy_from_slice(&written);
+        romdata.length = written.len();
+        Ok(CachedPatch {
+            romdata,
+            labels,
+            warnings,
+            written_blocks,
+            from_cache: true,
+        })
+    }
+
+    fn store(&self, key: &str, patch: &CachedPatch) -> io::Result<()> {
+        let mut encoder = Encoder::new(Vec::new());
+        encoder.u32(MAGIC)?;
+        encoder.u32(FORMAT_VERSION)?;
+        encoder.bytes(&patch.romdata.data[..patch.romdata.length])?;
+        encoder.list(&patch.labels, Encoder::label)?;
+        encoder.list(&patch.warnings, Encoder::error)?;
+        encoder.list(&patch.written_blocks, Encoder::written_block)?;
+
+        // write to a temporary file first, so that other processes never read a partial entry
+        let path = self.entry_path(key);
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&tmp, encoder.into_inner())?;
+        fs::rename(&tmp, &path)
+    }
+
+    /// Returns all the entries, with their size and last use time.
+    fn entries(&self) -> io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == ENTRY_EXTENSION) {
+                let metadata = fs::metadata(&path)?;
+                entries.push((path, metadata.len(), metadata.modified()?));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Removes the least recently used entries until the cache is within its limits.
+    fn evict(&self) -> io::Result<()> {
+        if self.max_entries.is_none() && self.max_bytes.is_none() {
+            return Ok(());
+        }
+        let mut entries = self.entries()?;
+        entries.sort_by_key(|(_, _, modified)| *modified);
+        let mut total_bytes = entries.iter().map(|(_, size, _)| size).sum::<u64>();
+        let mut count = entries.len();
+        for (path, size, _) in entries {
+            let over_entries = self.max_entries.is_some_and(|max| count > max);
+            let over_bytes = self.max_bytes.is_some_and(|max| total_bytes > max);
+            if !over_entries && !over_bytes {
+                break;
+            }
+            fs::remove_file(path)?;
+            count -= 1;
+            total_bytes -= size;
+        }
+        Ok(())
+    }
+}
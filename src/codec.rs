@@ -0,0 +1,196 @@
+//! Simple binary encoding of the bindings' types, used to store patch results and to talk to the worker processes.
+//!
+//! All integers are little endian, strings and byte buffers are prefixed by their length as a `u64`.
+use std::io::{self, Read, Write};
+
+use crate::{Define, ErrorData, Label, WrittenBlock};
+
+pub(crate) struct Encoder<W: Write> {
+    writer: W,
+}
+
+pub(crate) struct Decoder<R: Read> {
+    reader: R,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl<W: Write> Encoder<W> {
+    pub(crate) fn new(writer: W) -> Encoder<W> {
+        Encoder { writer }
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.writer
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) -> io::Result<()> {
+        self.writer.write_all(&[value as u8])
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) -> io::Result<()> {
+        self.writer.write_all(&[value])
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) -> io::Result<()> {
+        self.writer.write_all(&value.to_le_bytes())
+    }
+
+    pub(crate) fn i32(&mut self, value: i32) -> io::Result<()> {
+        self.writer.write_all(&value.to_le_bytes())
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) -> io::Result<()> {
+        self.writer.write_all(&value.to_le_bytes())
+    }
+
+    pub(crate) fn bytes(&mut self, value: &[u8]) -> io::Result<()> {
+        self.u64(value.len() as u64)?;
+        self.writer.write_all(value)
+    }
+
+    pub(crate) fn str(&mut self, value: &str) -> io::Result<()> {
+        self.bytes(value.as_bytes())
+    }
+
+    pub(crate) fn list<T, F>(&mut self, values: &[T], mut f: F) -> io::Result<()>
+    where
+        F: FnMut(&mut Self, &T) -> io::Result<()>,
+    {
+        self.u64(values.len() as u64)?;
+        for value in values {
+            f(self, value)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn label(&mut self, value: &Label) -> io::Result<()> {
+        self.str(&value.name)?;
+        self.i32(value.location)
+    }
+
+    pub(crate) fn define(&mut self, value: &Define) -> io::Result<()> {
+        self.str(&value.name)?;
+        self.str(&value.contents)
+    }
+
+    pub(crate) fn error(&mut self, value: &ErrorData) -> io::Result<()> {
+        self.str(&value.fullerrdata)?;
+        self.str(&value.rawerrdata)?;
+        self.str(&value.block)?;
+        self.str(&value.filename)?;
+        self.i32(value.line)?;
+        self.str(&value.callerfilename)?;
+        self.i32(value.callerline)?;
+        self.i32(value.errid)
+    }
+
+    pub(crate) fn written_block(&mut self, value: &WrittenBlock) -> io::Result<()> {
+        self.i32(value.pcoffset)?;
+        self.i32(value.snesoffset)?;
+        self.i32(value.numbytes)
+    }
+}
+
+impl<R: Read> Decoder<R> {
+    pub(crate) fn new(reader: R) -> Decoder<R> {
+        Decoder { reader }
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut buf = [0; N];
+        self.reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    pub(crate) fn bool(&mut self) -> io::Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid_data("invalid bool")),
+        }
+    }
+
+    pub(crate) fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn bytes(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.u64()?;
+        let mut buf = Vec::new();
+        (&mut self.reader).take(len).read_to_end(&mut buf)?;
+        if buf.len() as u64 != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(buf)
+    }
+
+    pub(crate) fn string(&mut self) -> io::Result<String> {
+        String::from_utf8(self.bytes()?).map_err(|_| invalid_data("invalid utf-8 string"))
+    }
+
+    pub(crate) fn list<T, F>(&mut self, mut f: F) -> io::Result<Vec<T>>
+    where
+        F: FnMut(&mut Self) -> io::Result<T>,
+    {
+        let len = self.u64()?;
+        let mut values = Vec::new();
+        for _ in 0..len {
+            values.push(f(self)?);
+        }
+        Ok(values)
+    }
+
+    pub(crate) fn label(&mut self) -> io::Result<Label> {
+        Ok(Label {
+            name: self.string()?,
+            location: self.i32()?,
+        })
+    }
+
+    pub(crate) fn define(&mut self) -> io::Result<Define> {
+        Ok(Define {
+            name: self.string()?,
+            contents: self.string()?,
+        })
+    }
+
+    pub(crate) fn error(&mut self) -> io::Result<ErrorData> {
+        Ok(ErrorData {
+            fullerrdata: self.string()?,
+            rawerrdata: self.string()?,
+            block: self.string()?,
+            filename: self.string()?,
+            line: self.i32()?,
+            callerfilename: self.string()?,
+            callerline: self.i32()?,
+            errid: self.i32()?,
+        })
+    }
+
+    pub(crate) fn written_block(&mut self) -> io::Result<WrittenBlock> {
+        Ok(WrittenBlock {
+            pcoffset: self.i32()?,
+            snesoffset: self.i32()?,
+            numbytes: self.i32()?,
+        })
+    }
+}
@@ -0,0 +1,495 @@
+//! Static scanner for the files a patch depends on.
+//!
+//! Asar reads the files included with `incsrc`, `incbin` and `table` by itself, so the bindings have no way to know
+//! which files were used by a patch. This module walks the entry file and resolves every include the way Asar does:
+//! first relative to the including file, then relative to each include path and each path listed in the
+//! [`PatchOption::StdIncludesFile`](crate::PatchOption::StdIncludesFile), checking [`PatchOption::MemoryFile`](crate::PatchOption::MemoryFile)s
+//! before the filesystem.
+//!
+//! The scan is purely textual: includes in conditional blocks or macros are always followed, and include paths built
+//! from defines are only resolved if the defines are passed as options or assigned with a plain `!name = value` line.
+//! Includes that cannot be resolved are reported by [`DependencyGraph::unresolved`].
+//!
+//! e.g.
+//! ```rust,no_run
+//! use asar_snes::{deps, AdvancedPatchOptions, PatchOption};
+//!
+//! let options = AdvancedPatchOptions::new().option(PatchOption::Include("asm/include".into()));
+//! let graph = deps::scan("asm/main.asm", &options).unwrap();
+//! for file in graph.files() {
+//!     println!("{}", file.path.display());
+//! }
+//! graph.write_depfile_to("main.d", "main.sfc").unwrap();
+//! ```
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{AdvancedPatchOptions, MemoryFileData};
+
+/// The way a file is included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DependencyKind {
+    /// The entry file of the patch.
+    Entry,
+    /// A file included with `incsrc`.
+    Source,
+    /// A file included with `incbin`.
+    Binary,
+    /// A file loaded with `table`.
+    Table,
+}
+
+/// A file the patch depends on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    /// The resolved path of the file.
+    pub path: PathBuf,
+    /// How the file was first included.
+    pub kind: DependencyKind,
+    /// Whether the file was resolved to a [`MemoryFile`](crate::MemoryFile) instead of a file on disk.
+    pub in_memory: bool,
+}
+
+/// A single include directive found while scanning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Include {
+    /// The file containing the directive.
+    pub from: PathBuf,
+    /// The line of the directive, starting from 1.
+    pub line: usize,
+    /// The kind of directive.
+    pub kind: DependencyKind,
+    /// The path as written in the directive, after define substitution.
+    pub target: String,
+    /// The path the directive was resolved to, None if it could not be resolved.
+    pub resolved: Option<PathBuf>,
+}
+
+/// The result of [`scan`], with all the files a patch depends on and the includes between them.
+#[derive(Debug, Clone)]
+pub struct DependencyGraph {
+    entry: PathBuf,
+    files: Vec<Dependency>,
+    includes: Vec<Include>,
+}
+
+impl DependencyGraph {
+    /// Returns the path of the entry file.
+    pub fn entry(&self) -> &Path {
+        &self.entry
+    }
+
+    /// Returns all the files the patch depends on, including the entry file, in the order they were found.
+    pub fn files(&self) -> &[Dependency] {
+        &self.files
+    }
+
+    /// Returns all the include directives found.
+    pub fn includes(&self) -> &[Include] {
+        &self.includes
+    }
+
+    /// Returns the include directives that could not be resolved.
+    pub fn unresolved(&self) -> impl Iterator<Item = &Include> {
+        self.includes.iter().filter(|i| i.resolved.is_none())
+    }
+
+    /// Returns the files directly included by `path`.
+    pub fn dependencies_of<'a>(&'a self, path: &'a Path) -> impl Iterator<Item = &'a Path> {
+        self.includes
+            .iter()
+            .filter(move |i| i.from == path)
+            .filter_map(|i| i.resolved.as_deref())
+    }
+
+    /// Returns the files on disk the patch depends on, including the entry file if it is not a memory file.
+    pub fn disk_files(&self) -> impl Iterator<Item = &Path> {
+        self.files
+            .iter()
+            .filter(|f| !f.in_memory)
+            .map(|f| f.path.as_path())
+    }
+
+    /// Writes a Makefile-style depfile for `target`, listing every file on disk the patch depends on.
+    ///
+    /// The output can be used by make (`-include main.d`) or ninja (`depfile = main.d`).
+    pub fn write_depfile<W: io::Write>(&self, mut writer: W, target: &str) -> io::Result<()> {
+        write!(writer, "{}:", escape_depfile_path(target))?;
+        for path in self.disk_files() {
+            write!(
+                writer,
+                " \\\n  {}",
+                escape_depfile_path(&path.to_string_lossy())
+            )?;
+        }
+        writeln!(writer)
+    }
+
+    /// Writes a Makefile-style depfile for `target` to the file at `path`.
+    ///
+    /// See [`DependencyGraph::write_depfile`].
+    pub fn write_depfile_to<P: AsRef<Path>>(&self, path: P, target: &str) -> io::Result<()> {
+        let mut contents = Vec::new();
+        self.write_depfile(&mut contents, target)?;
+        fs::write(path, contents)
+    }
+}
+
+fn escape_depfile_path(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            ' ' => escaped.push_str("\\ "),
+            '#' => escaped.push_str("\\#"),
+            '$' => escaped.push_str("$$"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Normalizes a path lexically, removing `.` components and resolving `..` components where possible.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            c => normalized.push(c.as_os_str()),
+        }
+    }
+    normalized
+}
+
+/// The files visible to Asar, the memory files of the patch and the filesystem.
+pub(crate) struct FileSystem<'a> {
+    memory_files: HashMap<PathBuf, &'a MemoryFileData>,
+}
+
+impl<'a> FileSystem<'a> {
+    pub(crate) fn new(options: &'a AdvancedPatchOptions) -> FileSystem<'a> {
+        FileSystem {
+            memory_files: options
+                .memory_files
+                .iter()
+                .map(|f| (normalize(Path::new(&f.filename)), &f.data))
+                .collect(),
+        }
+    }
+
+    /// Returns the normalized path and whether it is a memory file, if the file exists.
+    pub(crate) fn find(&self, path: &Path) -> Option<(PathBuf, bool)> {
+        let path = normalize(path);
+        if self.memory_files.contains_key(&path) {
+            Some((path, true))
+        } else if path.is_file() {
+            Some((path, false))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn read(&self, path: &Path, in_memory: bool) -> io::Result<String> {
+        if in_memory {
+            Ok(match self.memory_files[path] {
+                MemoryFileData::Text(text) => text.clone(),
+                MemoryFileData::Binary(data) => String::from_utf8_lossy(data).into_owned(),
+            })
+        } else {
+            fs::read(path).map(|data| String::from_utf8_lossy(&data).into_owned())
+        }
+    }
+}
+
+/// Scans `entry` and every file it includes, returning the dependency graph of the patch.
+///
+/// The include paths, defines and memory files are taken from `options`, so the same options passed to
+/// [`patching::patch_ex`](crate::patching::patch_ex) should be used.
+///
+/// Returns an error only if the entry file cannot be read, includes that cannot be resolved are reported by [`DependencyGraph::unresolved`].
+pub fn scan<P: AsRef<Path>>(
+    entry: P,
+    options: &AdvancedPatchOptions,
+) -> io::Result<DependencyGraph> {
+    let fs = FileSystem::new(options);
+    let mut includepaths = options
+        .includepaths
+        .iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    if let Some(file) = &options.stdincludesfile {
+        includepaths.extend(std_includepaths(Path::new(file)));
+    }
+    let defines = options
+        .additional_defines
+        .iter()
+        .map(|d| (d.name.clone(), d.contents.clone()))
+        .collect::<HashMap<_, _>>();
+
+    let (entry, in_memory) = fs.find(entry.as_ref()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} not found", entry.as_ref().display()),
+        )
+    })?;
+    let graph = DependencyGraph {
+        entry: entry.clone(),
+        files: vec![Dependency {
+            path: entry.clone(),
+            kind: DependencyKind::Entry,
+            in_memory,
+        }],
+        includes: Vec::new(),
+    };
+
+    let contents = fs.read(&entry, in_memory)?;
+    let mut scanner = Scanner {
+        fs,
+        includepaths,
+        defines,
+        visited: HashSet::from([entry.clone()]),
+        graph,
+    };
+    scanner.scan_file(&entry, &contents);
+    Ok(scanner.graph)
+}
+
+/// The state of [`scan`], shared by all the files of the patch.
+struct Scanner<'a> {
+    fs: FileSystem<'a>,
+    includepaths: Vec<PathBuf>,
+    defines: HashMap<String, String>,
+    visited: HashSet<PathBuf>,
+    graph: DependencyGraph,
+}
+
+impl Scanner<'_> {
+    /// Scans the statements of `file`, following each `incsrc` where it appears like Asar does, so that the defines
+    /// set by an included file are known by the rest of the including file.
+    fn scan_file(&mut self, file: &Path, contents: &str) {
+        for (line, statement) in statements(contents) {
+            let Some((kind, target)) = parse_statement(&statement, &mut self.defines) else {
+                continue;
+            };
+            let resolved = resolve(&self.fs, file, &self.includepaths, &target);
+            self.graph.includes.push(Include {
+                from: file.to_path_buf(),
+                line,
+                kind,
+                target,
+                resolved: resolved.as_ref().map(|(path, _)| path.clone()),
+            });
+            let Some((path, in_memory)) = resolved else {
+                continue;
+            };
+            if !self.visited.insert(path.clone()) {
+                continue;
+            }
+            self.graph.files.push(Dependency {
+                path: path.clone(),
+                kind,
+                in_memory,
+            });
+            if kind == DependencyKind::Source {
+                if let Ok(contents) = self.fs.read(&path, in_memory) {
+                    self.scan_file(&path, &contents);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the include paths listed in a std includes file, one per line and relative to the file itself.
+///
+/// Asar fails if the file cannot be read, so in that case no path is returned.
+fn std_includepaths(file: &Path) -> Vec<PathBuf> {
+    let Ok(contents) = fs::read_to_string(file) else {
+        return Vec::new();
+    };
+    let base = file.parent().unwrap_or(Path::new(""));
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| base.join(line))
+        .collect()
+}
+
+fn resolve(
+    fs: &FileSystem,
+    from: &Path,
+    includepaths: &[PathBuf],
+    target: &str,
+) -> Option<(PathBuf, bool)> {
+    if target.contains('!') {
+        return None;
+    }
+    let target = Path::new(target);
+    if target.is_absolute() {
+        return fs.find(target);
+    }
+    let base = from.parent().map(Path::to_path_buf).unwrap_or_default();
+    std::iter::once(base)
+        .chain(includepaths.iter().cloned())
+        .find_map(|dir| fs.find(&dir.join(target)))
+}
+
+/// Splits the source into statements, with the line they start on.
+///
+/// Comments are removed, lines ending with `\` are joined with the following one, and lines are split on ` : `.
+fn statements(contents: &str) -> Vec<(usize, String)> {
+    let mut statements = Vec::new();
+    let mut pending = String::new();
+    let mut pending_line = 0;
+    for (index, line) in contents.lines().enumerate() {
+        let line = strip_comment(line);
+        if pending.is_empty() {
+            pending_line = index + 1;
+        }
+        if let Some(continued) = line.trim_end().strip_suffix('\\') {
+            pending.push_str(continued);
+            continue;
+        }
+        pending.push_str(line);
+        for statement in split_statements(&pending) {
+            statements.push((pending_line, statement.trim().to_string()));
+        }
+        pending.clear();
+    }
+    if !pending.is_empty() {
+        statements.push((pending_line, pending.trim().to_string()));
+    }
+    statements
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn split_statements(line: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut in_string = false;
+    let mut start = 0;
+    let bytes = line.as_bytes();
+    for (index, &c) in bytes.iter().enumerate() {
+        match c {
+            b'"' => in_string = !in_string,
+            b':' if !in_string
+                && index > 0
+                && bytes[index - 1] == b' '
+                && bytes.get(index + 1) == Some(&b' ') =>
+            {
+                parts.push(&line[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&line[start..]);
+    parts
+}
+
+/// Parses a statement, returning the include it contains if any.
+///
+/// Simple define assignments are recorded in `defines`, so that they can be used in include paths.
+fn parse_statement(
+    statement: &str,
+    defines: &mut HashMap<String, String>,
+) -> Option<(DependencyKind, String)> {
+    if let Some(assignment) = statement.strip_prefix('!') {
+        if let Some((name, value)) = assignment.split_once('=') {
+            let name = name
+                .trim()
+                .trim_end_matches([':', '?', '+', '#'])
+                .trim_end();
+            if name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                let value = value.trim().trim_matches('"');
+                defines.insert(name.to_string(), substitute_defines(value, defines));
+            }
+        }
+        return None;
+    }
+
+    let (command, rest) = statement.split_once(char::is_whitespace)?;
+    let kind = match command.to_ascii_lowercase().as_str() {
+        "incsrc" => DependencyKind::Source,
+        "incbin" => DependencyKind::Binary,
+        "table" => DependencyKind::Table,
+        _ => return None,
+    };
+    let rest = substitute_defines(rest.trim(), defines);
+    let target = match rest.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next().unwrap_or_default().to_string(),
+        None => {
+            let target = rest.split_whitespace().next().unwrap_or_default();
+            let target = match kind {
+                // incbin file.bin:start-end
+                DependencyKind::Binary => match target.rsplit_once(':') {
+                    Some((path, range))
+                        if !range.is_empty()
+                            && range
+                                .chars()
+                                .all(|c| c.is_ascii_hexdigit() || c == '-' || c == '$') =>
+                    {
+                        path
+                    }
+                    _ => target,
+                },
+                // table file.tbl,rtl
+                DependencyKind::Table => target.split(',').next().unwrap_or_default(),
+                _ => target,
+            };
+            target.to_string()
+        }
+    };
+    if target.is_empty() {
+        None
+    } else {
+        Some((kind, target))
+    }
+}
+
+fn substitute_defines(text: &str, defines: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find('!') {
+        result.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        let (name, remaining) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.split_once('}') {
+                Some((name, remaining)) => (name, remaining),
+                None => (braced, ""),
+            }
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+        match defines.get(name) {
+            Some(value) => result.push_str(value),
+            None => {
+                result.push('!');
+                result.push_str(&rest[index + 1..rest.len() - remaining.len()]);
+            }
+        }
+        rest = remaining;
+    }
+    result.push_str(rest);
+    result
+}
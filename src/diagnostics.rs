@@ -0,0 +1,234 @@
+//! Rendering of errors and warnings with source snippets, in the style of rustc.
+//!
+//! [`ErrorData::fullerrdata`] is a single preformatted line, the [`Renderer`] instead shows the line of the error with a
+//! caret under the failing block, followed by the callers of the macro or file the error happened in.
+//! The source is read from the memory files of the patch first, then from disk.
+//!
+//! e.g.
+//! ```rust,no_run
+//! use asar_snes::diagnostics::{Renderer, Severity};
+//! use asar_snes::{AdvancedPatchOptions, PatchResult};
+//!
+//! let options = AdvancedPatchOptions::new();
+//! let renderer = Renderer::new().memory_files(&options);
+//! if let PatchResult::Failure(errors) = asar_snes::patching::patch_ex(vec![].into(), "main.asm", options) {
+//!     for error in &errors {
+//!         renderer.eprint(error, Severity::Error);
+//!     }
+//! }
+//! ```
+//!
+//! Which prints something like:
+//! ```text
+//! error[Eunknown_command]: Unknown command.
+//!  --> main.asm:3
+//!   |
+//! 3 |     foo bar
+//!   |     ^^^^^^^
+//!   = note: called from macros.asm:10
+//! ```
+use std::{
+    fmt::Write as _,
+    io::{self, IsTerminal, Write},
+    path::Path,
+};
+
+use crate::{deps::FileSystem, AdvancedPatchOptions, ErrorData};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// The severity of a rendered message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Whether the output of the [`Renderer`] uses colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    /// Uses colors when stderr is a terminal and the `NO_COLOR` environment variable is not set.
+    #[default]
+    Auto,
+    Always,
+    /// Plain text, suitable for logs.
+    Never,
+}
+
+/// Renders [`ErrorData`] with source snippets.
+///
+/// See the [module documentation](self) for more information.
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    options: AdvancedPatchOptions,
+    color: ColorChoice,
+}
+
+/// Splits the `(Eid): ` or `(Wid): ` tag Asar puts at the start of messages, returning the id and the message.
+pub(crate) fn split_tag(message: &str) -> (Option<&str>, &str) {
+    let Some(rest) = message.strip_prefix('(') else {
+        return (None, message);
+    };
+    match rest.split_once("):") {
+        Some((tag, message))
+            if tag.len() > 1
+                && tag.starts_with(['E', 'W'])
+                && tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+        {
+            (Some(tag), message.trim_start())
+        }
+        _ => (None, message),
+    }
+}
+
+/// Returns the `(Eid)` or `(Wid)` tag of an error, from [`ErrorData::rawerrdata`] or after the `error: ` or `warning: `
+/// of [`ErrorData::fullerrdata`].
+pub(crate) fn message_tag(error: &ErrorData) -> Option<&str> {
+    split_tag(&error.rawerrdata).0.or_else(|| {
+        ["error: ", "warning: "].iter().find_map(|level| {
+            let index = error.fullerrdata.find(level)?;
+            split_tag(&error.fullerrdata[index + level.len()..]).0
+        })
+    })
+}
+
+impl Renderer {
+    /// Creates a new Renderer, with automatic colors and reading the sources from disk.
+    pub fn new() -> Renderer {
+        Renderer {
+            options: AdvancedPatchOptions::new(),
+            color: ColorChoice::Auto,
+        }
+    }
+
+    /// Uses the memory files of `options` as sources, these should be the options of the patch operation.
+    pub fn memory_files(mut self, options: &AdvancedPatchOptions) -> Renderer {
+        self.options = options.clone();
+        self
+    }
+
+    /// Sets whether the output uses colors.
+    pub fn color(mut self, color: ColorChoice) -> Renderer {
+        self.color = color;
+        self
+    }
+
+    fn use_color(&self) -> bool {
+        match self.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none() && io::stderr().is_terminal()
+            }
+        }
+    }
+
+    fn source_line(&self, filename: &str, line: i32) -> Option<String> {
+        if filename.is_empty() || line < 1 {
+            return None;
+        }
+        let fs = FileSystem::new(&self.options);
+        let (path, in_memory) = fs.find(Path::new(filename))?;
+        let source = fs.read(&path, in_memory).ok()?;
+        source
+            .lines()
+            .nth(line as usize - 1)
+            .map(|l| l.trim_end().replace('\t', "    "))
+    }
+
+    /// Renders a message, ending with a newline.
+    pub fn render(&self, error: &ErrorData, severity: Severity) -> String {
+        let color = self.use_color();
+        let paint = |style: &'static str| if color { style } else { "" };
+        let reset = paint(RESET);
+        let (level, level_style) = match severity {
+            Severity::Error => ("error", RED),
+            Severity::Warning => ("warning", YELLOW),
+        };
+        let (level_style, gutter_style, bold) = (paint(level_style), paint(BLUE), paint(BOLD));
+
+        let (tag, message) = split_tag(&error.rawerrdata);
+        // messages without a tag are named after their errid, e.g. `[Eunknown_command]`
+        let id = match severity {
+            Severity::Error => error.error_id().map(|id| id.to_string()),
+            Severity::Warning => error.warning_id().map(|id| id.to_string()),
+        };
+        let tag = match tag.map(str::to_string).or(id) {
+            Some(tag) => format!("[{}]", tag),
+            None => String::new(),
+        };
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{level_style}{level}{tag}{reset}{bold}: {message}{reset}"
+        );
+        if error.filename.is_empty() {
+            return out;
+        }
+
+        let source = self.source_line(&error.filename, error.line);
+        let width = error.line.max(0).to_string().len();
+        let pad = " ".repeat(width);
+        let _ = writeln!(
+            out,
+            "{pad}{gutter_style}-->{reset} {}:{}",
+            error.filename, error.line
+        );
+        if let Some(source) = source {
+            let block = error.block.trim();
+            let (start, len) = match source.find(block).filter(|_| !block.is_empty()) {
+                Some(start) => (start, block.len()),
+                None => {
+                    let trimmed = source.trim_start();
+                    (source.len() - trimmed.len(), trimmed.len().max(1))
+                }
+            };
+            let _ = writeln!(out, "{pad} {gutter_style}|{reset}");
+            let _ = writeln!(
+                out,
+                "{gutter_style}{:>width$} |{reset} {source}",
+                error.line
+            );
+            let _ = writeln!(
+                out,
+                "{pad} {gutter_style}|{reset} {}{level_style}{}{reset}",
+                " ".repeat(start),
+                "^".repeat(len)
+            );
+        }
+        for frame in error.frames().iter().skip(1) {
+            let _ = writeln!(
+                out,
+                "{pad} {gutter_style}={reset} {bold}note{reset}: called from {}:{}",
+                frame.filename, frame.line
+            );
+        }
+        out
+    }
+
+    /// Writes a rendered message to `writer`.
+    pub fn write<W: Write>(
+        &self,
+        mut writer: W,
+        error: &ErrorData,
+        severity: Severity,
+    ) -> io::Result<()> {
+        writer.write_all(self.render(error, severity).as_bytes())
+    }
+
+    /// Prints a rendered message to stderr.
+    pub fn eprint(&self, error: &ErrorData, severity: Severity) {
+        let _ = self.write(io::stderr().lock(), error, severity);
+    }
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
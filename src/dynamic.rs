@@ -0,0 +1,233 @@
+//! Loading Asar as a shared library at runtime, with the `dynamic` feature.
+//!
+//! With this feature Asar is not built nor linked by the build script. Instead, the shared library is loaded the first
+//! time Asar is used, and the rest of the crate works exactly the same. The library is looked for in this order:
+//! - the path given to [`load`], if it was called before Asar was first used;
+//! - the path in the `ASAR_LIBRARY` environment variable;
+//! - `libasar.so`, `libasar.dylib` or `asar.dll`, found with the standard search of the platform.
+//!
+//! All the `asar_*` symbols are resolved when the library is loaded, and its API version is checked against the one
+//! of the bindings, so an incompatible library fails to load instead of being called with the wrong layouts.
+//! If the library cannot be loaded implicitly on first use, that first use panics with the [`AsarError`], except for the
+//! functions taking the global lock without blocking, which return it.
+//!
+//! Since Asar's state is global to the library, [`AsarInstance`] loads separate copies of it, each with its own state,
+//! so that patches can be assembled in parallel without processes.
+//!
+//! e.g.
+//! ```rust,no_run
+//! use asar_snes::{dynamic, AdvancedPatchOptions};
+//!
+//! if let Err(err) = dynamic::load("/opt/asar/lib/libasar.so") {
+//!     eprintln!("{}", err);
+//!     return;
+//! }
+//! println!("Asar {}", asar_snes::version());
+//!
+//! let handles = ["a.asm", "b.asm"].map(|patch| {
+//!     std::thread::spawn(move || {
+//!         let mut asar = dynamic::AsarInstance::new().unwrap();
+//!         asar.run(|| asar_snes::patching::patch_ex(vec![0; 0x80000].into(), patch, AdvancedPatchOptions::new()))
+//!     })
+//! });
+//! for handle in handles {
+//!     println!("{:?}", handle.join().unwrap());
+//! }
+//! ```
+use std::{
+    cell::Cell,
+    env,
+    ffi::{OsStr, OsString},
+    fs,
+    os::raw::{c_char, c_int},
+    path::{Path, PathBuf},
+    process, ptr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+};
+
+use crate::{
+    bindings::{
+        definedata, errordata, labeldata, mappertype, patchparams, writtenblockdata, AsarLibrary,
+    },
+    version, AsarError,
+};
+
+static LIBRARY: OnceLock<AsarLibrary> = OnceLock::new();
+
+thread_local! {
+    /// The library of the instance running on the current thread, see [`AsarInstance::run`].
+    static CURRENT: Cell<*const AsarLibrary> = const { Cell::new(ptr::null()) };
+}
+
+/// Returns the path of the library loaded when [`load`] was not called, from the `ASAR_LIBRARY` environment variable
+/// or the platform's file name for `asar`.
+pub fn default_library_path() -> OsString {
+    env::var_os("ASAR_LIBRARY").unwrap_or_else(|| libloading::library_filename("asar"))
+}
+
+/// Loads the Asar library from `path`, which can be a bare file name to use the standard search of the platform.
+///
+/// This must be called before Asar is first used, afterwards it returns [`AsarError::AlreadyLoaded`].
+pub fn load<P: AsRef<OsStr>>(path: P) -> Result<(), AsarError> {
+    let library = open(path.as_ref())?;
+    LIBRARY.set(library).map_err(|_| AsarError::AlreadyLoaded)
+}
+
+/// Returns whether the Asar library was loaded, either by [`load`] or by a previous use of Asar.
+pub fn is_loaded() -> bool {
+    LIBRARY.get().is_some()
+}
+
+/// Loads the library at `path` and checks that it is compatible with the bindings.
+pub(crate) fn open(path: &OsStr) -> Result<AsarLibrary, AsarError> {
+    let library = unsafe { AsarLibrary::new(path) }.map_err(AsarError::Load)?;
+    version::check_api_version(unsafe { library.asar_apiversion() })?;
+    Ok(library)
+}
+
+/// Loads the library from [`default_library_path`] if no library is loaded nor running on the current thread,
+/// returning the error instead of panicking on first use.
+pub(crate) fn ensure_loaded() -> Result<(), AsarError> {
+    if in_instance() || is_loaded() {
+        return Ok(());
+    }
+    let library = open(&default_library_path())?;
+    // another thread may have loaded it in the meantime, its library is kept
+    let _ = LIBRARY.set(library);
+    Ok(())
+}
+
+/// Returns the loaded library, loading it from [`default_library_path`] on the first call.
+fn library() -> &'static AsarLibrary {
+    LIBRARY.get_or_init(|| match open(&default_library_path()) {
+        Ok(library) => library,
+        Err(err) => panic!("{}", err),
+    })
+}
+
+/// Calls `f` with the library of the instance running on the current thread, or with the loaded library.
+fn with_library<T>(f: impl FnOnce(&AsarLibrary) -> T) -> T {
+    let current = CURRENT.get();
+    if current.is_null() {
+        f(library())
+    } else {
+        // the instance outlives its `run` call, which resets CURRENT before returning
+        f(unsafe { &*current })
+    }
+}
+
+/// Returns whether the current thread is running an [`AsarInstance`], which doesn't use the global lock.
+pub(crate) fn in_instance() -> bool {
+    !CURRENT.get().is_null()
+}
+
+/// A copy of the shared library in the temporary directory, removed when dropped.
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// An independent Asar, with its own labels, defines, errors and options.
+///
+/// Each instance loads its own copy of the shared library from a temporary file, since loading the same file twice
+/// would share its global state. Different instances can be used at the same time from different threads, without the
+/// global lock.
+///
+/// The functions of this crate use the instance while they are called from [`AsarInstance::run`]. Only one
+/// [`ApplyResult`](crate::ApplyResult) can be alive at a time across all instances, [`patching::patch_ex`](crate::patching::patch_ex)
+/// has no such limit.
+pub struct AsarInstance {
+    // dropped before the file is removed
+    library: AsarLibrary,
+    _file: TempFile,
+}
+
+impl AsarInstance {
+    /// Creates a new instance from a copy of the library at [`default_library_path`].
+    pub fn new() -> Result<AsarInstance, AsarError> {
+        AsarInstance::from_path(default_library_path())
+    }
+
+    /// Creates a new instance from a copy of the library at `path`.
+    ///
+    /// `path` must be the path of the library file, the standard search of the platform is not used.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<AsarInstance, AsarError> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = path.as_ref();
+        let name = path.file_name().unwrap_or(OsStr::new("asar"));
+        let mut copy_name = OsString::from(format!(
+            "asar-{}-{}-",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        copy_name.push(name);
+        let file = TempFile(env::temp_dir().join(copy_name));
+        fs::copy(path, &file.0).map_err(AsarError::Io)?;
+        Ok(AsarInstance {
+            library: open(file.0.as_os_str())?,
+            _file: file,
+        })
+    }
+
+    /// Runs `f` on the current thread with this instance, every call to Asar made by `f` uses it.
+    ///
+    /// Values that call Asar later, like [`ApplyResult`](crate::ApplyResult), must not be returned from `f`.
+    pub fn run<F, T>(&mut self, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        struct Restore(*const AsarLibrary);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT.set(self.0);
+            }
+        }
+
+        let _restore = Restore(CURRENT.replace(&self.library));
+        f()
+    }
+}
+
+macro_rules! forward {
+    ($(fn $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        /// Free functions with the same signatures as the linked bindings, calling into the loaded library.
+        pub(crate) mod symbols {
+            use super::*;
+
+            $(
+                pub(crate) unsafe fn $name($($arg: $ty),*) -> $ret {
+                    with_library(|library| library.$name($($arg),*))
+                }
+            )*
+        }
+    };
+}
+
+forward! {
+    fn asar_version() -> c_int;
+    fn asar_apiversion() -> c_int;
+    fn asar_reset() -> bool;
+    fn asar_patch(patchloc: *const c_char, romdata: *mut c_char, buflen: c_int, romlen: *mut c_int) -> bool;
+    fn asar_patch_ex(params: *const patchparams) -> bool;
+    fn asar_maxromsize() -> c_int;
+    fn asar_geterrors(count: *mut c_int) -> *const errordata;
+    fn asar_getwarnings(count: *mut c_int) -> *const errordata;
+    fn asar_getprints(count: *mut c_int) -> *const *const c_char;
+    fn asar_getalllabels(count: *mut c_int) -> *const labeldata;
+    fn asar_getlabelval(name: *const c_char) -> c_int;
+    fn asar_getdefine(name: *const c_char) -> *const c_char;
+    fn asar_getalldefines(count: *mut c_int) -> *const definedata;
+    fn asar_resolvedefines(data: *const c_char, learnnew: bool) -> *const c_char;
+    fn asar_math(math: *const c_char, error: *mut *const c_char) -> f64;
+    fn asar_getwrittenblocks(count: *mut c_int) -> *const writtenblockdata;
+    fn asar_getmapper() -> mappertype;
+    fn asar_getsymbolsfile(format: *const c_char) -> *const c_char;
+}
@@ -0,0 +1,312 @@
+//! The errors Asar can report, see [`ErrorId`].
+use core::fmt;
+
+/// The kind of problem an [`ErrorId`] reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// Invalid parameters passed to the library, or invalid command-line options.
+    Api,
+    /// Files that can't be found or read.
+    Io,
+    /// Malformed source code.
+    Syntax,
+    /// Errors while evaluating math expressions and functions.
+    Math,
+    Label,
+    Define,
+    Macro,
+    /// Blocks that are not opened or closed properly, like `if`/`endif` or `pushpc`/`pullpc`.
+    Block,
+    /// Opcodes, addressing modes and branches.
+    Assembly,
+    /// Addresses that don't fit the current mapper or the ROM.
+    Mapper,
+    Freespace,
+    /// Errors raised on purpose by the patch, with `assert`, `error` or `warnpc`.
+    User,
+    /// Errors in Asar itself, or limits of Asar.
+    Internal,
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorCategory::Api => "api",
+            ErrorCategory::Io => "io",
+            ErrorCategory::Syntax => "syntax",
+            ErrorCategory::Math => "math",
+            ErrorCategory::Label => "label",
+            ErrorCategory::Define => "define",
+            ErrorCategory::Macro => "macro",
+            ErrorCategory::Block => "block",
+            ErrorCategory::Assembly => "assembly",
+            ErrorCategory::Mapper => "mapper",
+            ErrorCategory::Freespace => "freespace",
+            ErrorCategory::User => "user",
+            ErrorCategory::Internal => "internal",
+        };
+        f.write_str(name)
+    }
+}
+
+macro_rules! error_ids {
+    ($($variant:ident = $errid:literal, $name:literal, $category:ident, $explanation:literal;)*) => {
+        /// An error defined by Asar 1.91, with its numeric id, its name, its category and an explanation.
+        ///
+        /// The numeric ids follow the declaration order of the errors in Asar's `errors.h`.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum ErrorId {
+            $($variant,)*
+        }
+
+        impl ErrorId {
+            /// All the errors, in the order of their numeric ids.
+            pub const ALL: &'static [ErrorId] = &[$(ErrorId::$variant,)*];
+
+            /// Returns the numeric id of the error, as found in [`ErrorData::errid`](crate::ErrorData::errid).
+            pub fn errid(self) -> i32 {
+                match self {
+                    $(ErrorId::$variant => $errid,)*
+                }
+            }
+
+            /// Returns the name of the error, without the `E` prefix.
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(ErrorId::$variant => $name,)*
+                }
+            }
+
+            /// Returns the category of the error.
+            pub fn category(self) -> ErrorCategory {
+                match self {
+                    $(ErrorId::$variant => ErrorCategory::$category,)*
+                }
+            }
+
+            /// Returns a longer explanation of the error and how to fix it, like `rustc --explain`.
+            pub fn explain(self) -> &'static str {
+                match self {
+                    $(ErrorId::$variant => $explanation,)*
+                }
+            }
+        }
+    };
+}
+
+// Every `error_id_*` enumerator of `src/asar/src/asar/errors.h` in Asar 1.91, in declaration order, numbered from
+// `error_id_start + 1`. `test_error_ids_match_header` compares this list with the header of the submodule.
+error_ids! {
+    LimitReached = 5001, "limit_reached", Internal,
+        "Too many errors were reported, so Asar stopped assembling to avoid flooding the output. Fix the errors reported before this one.";
+    Werror = 5002, "werror", User,
+        "A warning was reported while warnings are treated as errors, with the `-werror` option or `warnings errors on`. Fix the warning, or disable it with `-wno<name>`.";
+    BufferTooSmall = 5003, "buffer_too_small", Api,
+        "The ROM buffer passed to Asar is smaller than the assembled ROM. Pass a buffer of `max_rom_size()` bytes, Asar never grows it.";
+    ParamsNull = 5004, "params_null", Api,
+        "The library was called without patch parameters.";
+    ParamsInvalidSize = 5005, "params_invalid_size", Api,
+        "The size of the patch parameters doesn't match the one the Asar library expects. The bindings were generated for a different version of Asar.";
+    ParamsNullFilename = 5006, "params_null_filename", Api,
+        "The library was called without the name of the patch to assemble.";
+    StddefinesNoIdentifier = 5007, "stddefines_no_identifier", Api,
+        "A line of the standard defines file doesn't start with a define name. Every line should look like `name = value` or just `name`.";
+    StddefineAfterClosingQuote = 5008, "stddefine_after_closing_quote", Api,
+        "A value in the standard defines file has text after its closing quote.";
+    CmdlDefineInvalid = 5009, "cmdl_define_invalid", Api,
+        "A define passed on the command line or as an additional define has an invalid name. Define names may only contain letters, digits and underscores.";
+    CmdlDefineOverride = 5010, "cmdl_define_override", Api,
+        "The same define was passed more than once on the command line, as an additional define or in the standard defines file.";
+    FailedToOpenFile = 5011, "failed_to_open_file", Io,
+        "The file exists, but Asar couldn't open it. Check that it isn't locked by another program and that it can be read.";
+    FileNotFound = 5012, "file_not_found", Io,
+        "The file couldn't be found. Asar looks for included files relative to the file that includes them, then in each include path, in order.";
+    ReadfileOneToFourBytes = 5013, "readfile_1_to_4_bytes", Io,
+        "The `readfile` functions can only read between 1 and 4 bytes at a time.";
+    CanreadfileZeroBytes = 5014, "canreadfile_0_bytes", Io,
+        "`canreadfile` needs to check for at least one byte.";
+    FileOffsetOutOfBounds = 5015, "file_offset_out_of_bounds", Io,
+        "The offset passed to a `readfile` function is past the end of the file. Use `canreadfile` to check the size first.";
+    OpenRomFailed = 5016, "open_rom_failed", Io,
+        "The ROM file couldn't be opened, or is larger than the maximum ROM size Asar supports.";
+    CreateRomFailed = 5017, "create_rom_failed", Io,
+        "The ROM file didn't exist and Asar couldn't create it. Check that the directory exists and can be written.";
+    CantBeMainFile = 5018, "cant_be_main_file", Io,
+        "The file uses `includefrom`, so it is meant to be included by another file and can't be assembled on its own. Assemble the file it names instead.";
+    SnesAddressDoesntMapToRom = 5019, "snes_address_doesnt_map_to_rom", Mapper,
+        "The SNES address doesn't map to ROM with the current mapper, e.g. RAM addresses or addresses below $8000 in LoROM. Check the `org` or the mapper command.";
+    SnesAddressOutOfBounds = 5020, "snes_address_out_of_bounds", Mapper,
+        "The SNES address is outside of the ROM sizes supported by the current mapper.";
+    InvalidMapper = 5021, "invalid_mapper", Mapper,
+        "The mapper can't be used here. The mapper must be set before any code is written, and only once per patch.";
+    RomTooShort = 5022, "rom_too_short", Mapper,
+        "`check title` was used, but the ROM is too short to have a title at all.";
+    RomTitleIncorrect = 5023, "rom_title_incorrect", Mapper,
+        "`check title` was used, and the title of the ROM doesn't match. The patch was probably applied to the wrong ROM.";
+    BankBorderCrossed = 5024, "bank_border_crossed", Mapper,
+        "The code or data crossed a bank border while `check bankcross` is enabled. Move it to a different address, or use `check bankcross off` if this is intended.";
+    InvalidNumber = 5025, "invalid_number", Syntax,
+        "A number was expected here. Hex numbers start with `$`, binary numbers with `%`.";
+    InvalidHexValue = 5026, "invalid_hex_value", Syntax,
+        "The hex number contains characters other than 0-9 and A-F.";
+    InvalidBinaryValue = 5027, "invalid_binary_value", Syntax,
+        "The binary number contains characters other than 0 and 1.";
+    InvalidCharacter = 5028, "invalid_character", Syntax,
+        "The character literal is invalid, it must contain exactly one character between single quotes.";
+    GarbageNearQuotedString = 5029, "garbage_near_quoted_string", Syntax,
+        "There is unexpected text right before or after a quoted string. Separate the string from the rest with a comma or a space.";
+    MismatchedQuotes = 5030, "mismatched_quotes", Syntax,
+        "A string is not closed on the same line it was opened.";
+    MismatchedParentheses = 5031, "mismatched_parentheses", Syntax,
+        "There are more opening than closing parentheses, or the other way around.";
+    UnclosedBlockComment = 5032, "unclosed_block_comment", Syntax,
+        "A `;[[` block comment was never closed with `]]`.";
+    UnknownCommand = 5033, "unknown_command", Syntax,
+        "The line isn't a known command, opcode, label or define. Check for typos, and that the macros and defines it uses are defined before it.";
+    BrokenCommand = 5034, "broken_command", Syntax,
+        "The command is known, but its arguments are wrong. Check the Asar manual for its syntax.";
+    StartOfFile = 5035, "start_of_file", Syntax,
+        "This command must be at the start of the file, before any other command.";
+    InvalidVersionNumber = 5036, "invalid_version_number", Syntax,
+        "The version passed to the `asar` command is not a valid version number.";
+    AsarTooOld = 5037, "asar_too_old", Syntax,
+        "The patch requires a newer version of Asar, as stated by its `asar` command.";
+    UnknownOperator = 5038, "unknown_operator", Math,
+        "The math expression contains an operator Asar doesn't know.";
+    InvalidInput = 5039, "invalid_input", Math,
+        "The math expression is malformed, e.g. it ends with an operator or has two values in a row.";
+    DivisionByZero = 5040, "division_by_zero", Math,
+        "The math expression divides by zero.";
+    ModuloByZero = 5041, "modulo_by_zero", Math,
+        "The math expression computes a modulo by zero.";
+    UnknownFunction = 5042, "unknown_function", Math,
+        "The function doesn't exist. Functions must be defined with `function` before being used.";
+    MalformedFunctionCall = 5043, "malformed_function_call", Math,
+        "The function call is malformed, the arguments must be in parentheses and separated by commas.";
+    InvalidNumberOfArguments = 5044, "invalid_number_of_arguments", Math,
+        "The function was called with the wrong number of arguments.";
+    LabelNotFound = 5045, "label_not_found", Label,
+        "The label doesn't exist. Labels can be used before they are defined, but sublabels and `+`/`-` labels are only visible in their own scope.";
+    LabelRedefined = 5046, "label_redefined", Label,
+        "The label was already defined. Every label must be unique, use sublabels or namespaces for names that repeat.";
+    LabelMoving = 5047, "label_moving", Label,
+        "The label changed position between passes, usually because code before it changes size depending on the label. Give the operand an explicit size, e.g. `lda.l`.";
+    LabelCrossAssignment = 5048, "label_cross_assignment", Label,
+        "Labels can't be assigned from labels that are defined later in the file.";
+    LabelOnThirdPass = 5049, "label_on_third_pass", Label,
+        "A label could only be resolved on the last pass. This usually means labels are defined inside of conditionals that depend on other labels.";
+    LabelAmbiguous = 5050, "label_ambiguous", Label,
+        "The `+`/`-` label is ambiguous here, use a named label instead.";
+    BrokenLabelDefinition = 5051, "broken_label_definition", Label,
+        "The label definition is malformed, label names may only contain letters, digits and underscores.";
+    LabelInConditional = 5052, "label_in_conditional", Label,
+        "Labels can't be used in conditionals, since their value isn't known on the first pass. Use defines instead.";
+    MacroLabelOutsideOfMacro = 5053, "macro_label_outside_of_macro", Label,
+        "`?` macro labels can only be used inside of a macro.";
+    InvalidNamespaceName = 5054, "invalid_namespace_name", Label,
+        "The namespace name is invalid, namespace names follow the same rules as labels.";
+    DefineNotFound = 5055, "define_not_found", Define,
+        "The define doesn't exist. Defines must be defined before they are used, unlike labels.";
+    BrokenDefineDeclaration = 5056, "broken_define_declaration", Define,
+        "The define declaration is malformed, it should look like `!name = value`.";
+    OverridingBuiltinDefine = 5057, "overriding_builtin_define", Define,
+        "Built-in defines like `!assembler` can't be redefined.";
+    DefineLabelMath = 5058, "define_label_math", Define,
+        "Defines assigned with `#=` are evaluated on the spot, so they can't use labels.";
+    MacroNotFound = 5059, "macro_not_found", Macro,
+        "The macro doesn't exist. Macros must be defined before they are called.";
+    MacroWrongMinParams = 5060, "macro_wrong_min_params", Macro,
+        "The variadic macro was called with fewer arguments than its named parameters.";
+    MacroWrongNumParams = 5061, "macro_wrong_num_params", Macro,
+        "The macro was called with a different number of arguments than it has parameters.";
+    InvalidMacroParamName = 5062, "invalid_macro_param_name", Macro,
+        "The macro parameter name is invalid, parameter names follow the same rules as labels.";
+    MacroParamNotFound = 5063, "macro_param_not_found", Macro,
+        "The `<name>` parameter doesn't exist in this macro.";
+    MacroParamRedefined = 5064, "macro_param_redefined", Macro,
+        "The macro has two parameters with the same name.";
+    NestedMacroDefinition = 5065, "nested_macro_definition", Macro,
+        "Macros can't be defined inside of other macros.";
+    MacroRedefined = 5066, "macro_redefined", Macro,
+        "A macro with the same name was already defined.";
+    MisplacedEndmacro = 5067, "misplaced_endmacro", Macro,
+        "`endmacro` was found outside of a macro definition.";
+    UnclosedMacro = 5068, "unclosed_macro", Macro,
+        "The macro definition was never closed with `endmacro`.";
+    RecursionLimit = 5069, "recursion_limit", Macro,
+        "Macros or includes are nested too deeply, usually because of a macro that calls itself without an exit condition.";
+    PushpcWithoutPullpc = 5070, "pushpc_without_pullpc", Block,
+        "A `pushpc` is never matched by a `pullpc` before the end of the file.";
+    PullpcWithoutPushpc = 5071, "pullpc_without_pushpc", Block,
+        "`pullpc` was used without a previous `pushpc`.";
+    PushbaseWithoutPullbase = 5072, "pushbase_without_pullbase", Block,
+        "A `pushbase` is never matched by a `pullbase` before the end of the file.";
+    PullbaseWithoutPushbase = 5073, "pullbase_without_pushbase", Block,
+        "`pullbase` was used without a previous `pushbase`.";
+    PulltableWithoutTable = 5074, "pulltable_without_table", Block,
+        "`pulltable` was used without a previous `pushtable`.";
+    MisplacedElseif = 5075, "misplaced_elseif", Block,
+        "`elseif` was found outside of an `if` block, or after its `else`.";
+    MisplacedElse = 5076, "misplaced_else", Block,
+        "`else` was found outside of an `if` block, or after another `else`.";
+    MisplacedEndif = 5077, "misplaced_endif", Block,
+        "`endif` was found outside of an `if` block.";
+    UnclosedIf = 5078, "unclosed_if", Block,
+        "An `if` block was never closed with `endif` before the end of the file.";
+    MissingOrg = 5079, "missing_org", Block,
+        "Code or data was written before any `org` or `freespace` command, so Asar doesn't know where to put it.";
+    RelativeBranchOutOfBounds = 5080, "relative_branch_out_of_bounds", Assembly,
+        "The branch target is too far away, branches can only jump -128 to 127 bytes. Use `brl` or a `jmp`/`jml` instead.";
+    InvalidOpcodeLength = 5081, "invalid_opcode_length", Assembly,
+        "The opcode doesn't exist with this length suffix, e.g. `.l` on an instruction with no long addressing mode.";
+    BadAddrMode = 5082, "bad_addr_mode", Assembly,
+        "The opcode doesn't support this addressing mode.";
+    BadAccessWidth = 5083, "bad_access_width", Assembly,
+        "The operand is too large for the addressing mode of the opcode.";
+    NoFreespace = 5084, "no_freespace", Freespace,
+        "Asar couldn't find enough free space in the ROM. Expand the ROM, or free some space.";
+    FreespaceLimitReached = 5085, "freespace_limit_reached", Freespace,
+        "The patch uses more freespace blocks than Asar supports.";
+    StaticFreespaceAutoclean = 5086, "static_freespace_autoclean", Freespace,
+        "Static freespace blocks can't be cleaned with `autoclean`.";
+    StaticFreespaceGrowing = 5087, "static_freespace_growing", Freespace,
+        "A static freespace block is larger than when the patch was first applied. Static blocks can't grow, since they can't move.";
+    BrokenAutoclean = 5088, "broken_autoclean", Freespace,
+        "`autoclean` must be followed by an instruction with a label operand, or by `dl` with a label.";
+    AutocleanLabelAtFreespaceEnd = 5089, "autoclean_label_at_freespace_end", Freespace,
+        "The label used by `autoclean` points to the end of its freespace block, so Asar can't tell which block it belongs to.";
+    PadInFreespace = 5090, "pad_in_freespace", Freespace,
+        "`pad` can't be used in freespace, since the final address of the block isn't known.";
+    AssertionFailed = 5091, "assertion_failed", User,
+        "An `assert` in the patch failed.";
+    ErrorCommand = 5092, "error_command", User,
+        "The patch used the `error` command.";
+    WarnpcFailed = 5093, "warnpc_failed", User,
+        "The code went past the address passed to `warnpc`, it probably overwrites something that follows it.";
+    WarnpcFailedEqual = 5094, "warnpc_failed_equal", User,
+        "The code reached exactly the address passed to `warnpc`. `warnpc` fails when the address is reached, not only when it is passed.";
+    InternalError = 5095, "internal_error", Internal,
+        "Asar reached a state that should be impossible. Please report it to the Asar developers with the patch that caused it.";
+}
+
+impl ErrorId {
+    /// Returns the error with the numeric id `errid`.
+    pub fn from_errid(errid: i32) -> Option<ErrorId> {
+        ErrorId::ALL.iter().copied().find(|e| e.errid() == errid)
+    }
+
+    /// Parses an error as written in Asar's messages, e.g. `Eunknown_command`, `unknown_command` or `E5033`.
+    pub fn from_name(name: &str) -> Option<ErrorId> {
+        let name = name.strip_prefix('E').unwrap_or(name);
+        match name.parse::<i32>() {
+            Ok(errid) => ErrorId::from_errid(errid),
+            Err(_) => ErrorId::ALL.iter().copied().find(|e| e.name() == name),
+        }
+    }
+}
+
+impl fmt::Display for ErrorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "E{}", self.name())
+    }
+}
@@ -4,29 +4,62 @@
 //! By default this crate is not thread-safe.
 //!
 //! In case it is needed to use this crate in a multithreaded environment, the `thread-safe` feature should be enabled, doing so will make all the functions use a global lock to ensure that the Asar API is called in a thread-safe manner.
+//!
+//! The `cache` feature enables the [`cache`] module, to skip patch operations whose inputs didn't change.
+//!
+//! The `dynamic` feature loads Asar as a shared library at runtime instead of building it, see the `dynamic` module.
+//! The `system-asar` feature links an Asar library that is already installed, found in `ASAR_LIB_DIR` or with pkg-config.
+//! The bindings checked in the crate are used unless the `bindgen` feature is enabled, which regenerates them from the
+//! Asar header and needs libclang.
 pub(crate) mod bindings {
     #![allow(non_upper_case_globals)]
     #![allow(non_camel_case_types)]
     #![allow(non_snake_case)]
+    #[cfg(feature = "bindgen")]
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+    #[cfg(all(not(feature = "bindgen"), not(feature = "dynamic")))]
+    include!("bindings.rs");
+    #[cfg(all(not(feature = "bindgen"), feature = "dynamic"))]
+    include!("bindings_dynamic.rs");
+
+    #[cfg(feature = "dynamic")]
+    pub(crate) use crate::dynamic::symbols::*;
 }
 
+pub mod actor;
+pub mod build;
+#[cfg(feature = "cache")]
+pub mod cache;
+mod codec;
+pub mod deps;
+pub mod diagnostics;
+#[cfg(feature = "dynamic")]
+pub mod dynamic;
+pub mod errors;
+pub mod lock;
+pub mod version;
+pub mod warnings;
+pub mod watch;
+pub mod worker;
+
 #[cfg(test)]
 mod test;
 
 extern crate asar_snes_proc_macros;
+extern crate self as asar_snes;
 pub use asar_snes_proc_macros::use_asar_global_lock;
+pub use asar_snes_proc_macros::{AsarDefines, DefineValue, FromAsarLabels};
+pub use errors::{ErrorCategory, ErrorId};
+pub use version::Version;
+pub use warnings::WarningId;
 
 use core::fmt;
-#[cfg(feature = "thread-safe")]
-use parking_lot::ReentrantMutex;
-#[cfg(feature = "thread-safe")]
-use std::sync::OnceLock;
 
 use std::{
     ffi::{CStr, CString},
     os::raw::{c_char, c_int, c_void},
     ptr,
+    time::Duration,
 };
 
 use crate::bindings::{
@@ -37,27 +70,6 @@ use crate::bindings::{
     patchparams, warnsetting, writtenblockdata,
 };
 
-#[cfg(feature = "thread-safe")]
-fn global_asar_lock() -> &'static ReentrantMutex<()> {
-    static LOCK: OnceLock<ReentrantMutex<()>> = OnceLock::new();
-    LOCK.get_or_init(|| ReentrantMutex::new(()))
-}
-
-#[cfg(not(feature = "thread-safe"))]
-fn global_asar_lock() -> &'static FakeLock {
-    &FakeLock
-}
-
-#[cfg(not(feature = "thread-safe"))]
-struct FakeLock;
-
-#[cfg(not(feature = "thread-safe"))]
-impl FakeLock {
-    fn lock(&self) -> FakeLock {
-        FakeLock
-    }
-}
-
 /// Executes the closure with the Asar global lock.
 ///
 /// This lock is recursive, so it can be used in nested calls without issues.
@@ -85,17 +97,53 @@ impl FakeLock {
 /// ```
 ///
 /// A lot of functions already use this lock internally, but if you are calling multiple functions in a row, it is recommended to call it manually since other threads might interfere between the calls.
-/// 
+///
+/// If `f` panics, Asar is reset before the lock is released, so the next caller doesn't see the labels, defines and errors
+/// of the interrupted operation. This also applies to the functions using [`use_asar_global_lock`].
+///
 /// # Note
-/// This function does something **only** if the `thread-safe` feature is **enabled**. Otherwise it is a no-op.
+/// The lock is taken **only** if the `thread-safe` feature is **enabled**. Otherwise this function just calls the closure.
+///
+/// # Panics
+/// Panics if the Asar library is not compatible with the bindings, see [`version`](mod@version).
+/// [`try_with_asar_lock`] returns the error instead.
 pub fn with_asar_lock<F, R>(f: F) -> R
 where
     F: FnOnce() -> R,
 {
-    let _lock = global_asar_lock().lock();
+    let _lock = lock::acquire();
     f()
 }
 
+/// Executes the closure with the Asar global lock if it is available, without blocking.
+///
+/// Returns [`TryLockError::Busy`](lock::TryLockError::Busy) if another thread holds the lock. The lock is recursive,
+/// so this always succeeds if the current thread already holds it. Returns [`TryLockError::Asar`](lock::TryLockError::Asar)
+/// if the Asar library is not compatible with the bindings, see [`version`](mod@version).
+///
+/// See [`with_asar_lock`] for more information.
+pub fn try_with_asar_lock<F, R>(f: F) -> Result<R, lock::TryLockError>
+where
+    F: FnOnce() -> R,
+{
+    let _lock = lock::try_acquire(None)?;
+    Ok(f())
+}
+
+/// Executes the closure with the Asar global lock, waiting at most `timeout` for it to be available.
+///
+/// Returns [`TryLockError::Busy`](lock::TryLockError::Busy) if another thread still holds the lock after `timeout`,
+/// and [`TryLockError::Asar`](lock::TryLockError::Asar) if the Asar library is not compatible with the bindings.
+///
+/// See [`with_asar_lock`] for more information.
+pub fn with_asar_lock_timeout<F, R>(timeout: Duration, f: F) -> Result<R, lock::TryLockError>
+where
+    F: FnOnce() -> R,
+{
+    let _lock = lock::try_acquire(Some(timeout))?;
+    Ok(f())
+}
+
 /// Represents the ROM data, with a byte vector and the ROM length
 ///
 /// Note that the ROM length may not be the same as the length of the data vector, it is the actual length of the ROM.
@@ -125,6 +173,14 @@ pub struct ErrorData {
 /// Represents a warning message from Asar.
 pub type WarningData = ErrorData;
 
+/// Represents a frame of the call stack of an error, see [`ErrorData::frames`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    pub filename: String,
+    pub line: i32,
+    pub block: String,
+}
+
 /// Represents a define from Asar, with its name and contents.
 #[derive(Debug, Clone)]
 pub struct Define {
@@ -210,6 +266,7 @@ pub struct AdvancedPatchOptions {
     memory_files: Vec<MemoryFile>,
     override_checksum_gen: bool,
     generate_checksum: bool,
+    warning_policy: warnings::WarningPolicy,
 }
 
 pub type MapperType = mappertype;
@@ -235,6 +292,8 @@ pub enum PatchOption {
     Define(String, String),
     /// Adds a warning setting to the patch operation.
     Warning(String, bool),
+    /// Adds a warning setting to the patch operation, using a typed [`WarningId`].
+    SetWarning(WarningId, bool),
     /// Adds a memory file to the patch operation.
     MemoryFile(String, MemoryFileData),
     /// Adds a standard includes file to the patch operation.
@@ -247,8 +306,185 @@ pub enum PatchOption {
     GenerateChecksum(bool),
     /// Sets whether the patch operation should reset.
     ShouldReset(bool),
+    /// Sets which warnings fail the patch operation, see [`WarningPolicy`](warnings::WarningPolicy).
+    WarningPolicy(warnings::WarningPolicy),
+}
+
+/// Types that can be turned into a list of [`PatchOption::Define`], usually implemented with `#[derive(AsarDefines)]`.
+///
+/// See the [`AsarDefines`](derive@AsarDefines) derive macro for more information.
+pub trait AsarDefines {
+    /// Returns a [`PatchOption::Define`] for each define of this value.
+    fn to_defines(&self) -> Vec<PatchOption>;
+}
+
+/// Types that can be used as the contents of a define.
+///
+/// Enums without fields can implement it with `#[derive(DefineValue)]`.
+pub trait DefineValue {
+    /// Returns the contents of the define, or None if the define should not be added.
+    fn define_value(&self) -> Option<String>;
+
+    /// Returns the contents of the define formatted as a `$`-prefixed hex number padded to `width` digits.
+    ///
+    /// Types that are not numbers return the same value as [`DefineValue::define_value`].
+    fn hex_define_value(&self, width: usize) -> Option<String> {
+        let _ = width;
+        self.define_value()
+    }
+}
+
+macro_rules! impl_define_value_int {
+    ($($t:ty),*) => {
+        $(
+            impl DefineValue for $t {
+                fn define_value(&self) -> Option<String> {
+                    Some(self.to_string())
+                }
+                fn hex_define_value(&self, width: usize) -> Option<String> {
+                    Some(format!("${:0width$X}", self, width = width))
+                }
+            }
+        )*
+    };
+}
+
+impl_define_value_int!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+impl DefineValue for bool {
+    fn define_value(&self) -> Option<String> {
+        Some(if *self { "1" } else { "0" }.into())
+    }
+}
+
+impl DefineValue for String {
+    fn define_value(&self) -> Option<String> {
+        Some(self.clone())
+    }
+}
+
+impl DefineValue for &str {
+    fn define_value(&self) -> Option<String> {
+        Some((*self).into())
+    }
+}
+
+impl DefineValue for char {
+    fn define_value(&self) -> Option<String> {
+        Some(self.to_string())
+    }
+}
+
+impl<T: DefineValue> DefineValue for Option<T> {
+    fn define_value(&self) -> Option<String> {
+        self.as_ref().and_then(DefineValue::define_value)
+    }
+    fn hex_define_value(&self, width: usize) -> Option<String> {
+        self.as_ref().and_then(|v| v.hex_define_value(width))
+    }
+}
+
+/// Types that can be built from the labels of a patch operation, usually implemented with `#[derive(FromAsarLabels)]`.
+///
+/// See the [`FromAsarLabels`](derive@FromAsarLabels) derive macro for more information.
+pub trait FromAsarLabels: Sized {
+    /// Builds the value from the labels provided, e.g. from [`ApplyResult::labels`].
+    fn from_labels(labels: &[Label]) -> Result<Self, MissingLabelsError>;
+
+    /// Builds the value from the labels of the latest api call (usually [`patching::patch`] or [`patching::patch_ex`]).
+    ///
+    /// remarks: This function uses the global lock.
+    fn from_asar() -> Result<Self, MissingLabelsError> {
+        Self::from_labels(&patching::labels())
+    }
 }
 
+/// Types that can be used as fields of a [`FromAsarLabels`] struct.
+pub trait LabelValue: Sized {
+    /// Converts the location of a label (None if the label was not found) into the value of the field.
+    ///
+    /// Returns None if the label is required but it was not found.
+    fn from_label_value(location: Option<i32>) -> Option<Self>;
+}
+
+macro_rules! impl_label_value_int {
+    ($($t:ty),*) => {
+        $(
+            impl LabelValue for $t {
+                fn from_label_value(location: Option<i32>) -> Option<Self> {
+                    location.map(|location| location as $t)
+                }
+            }
+        )*
+    };
+}
+
+impl_label_value_int!(u32, u64, usize, i32, i64, isize);
+
+impl<T: LabelValue> LabelValue for Option<T> {
+    fn from_label_value(location: Option<i32>) -> Option<Self> {
+        Some(location.and_then(|location| T::from_label_value(Some(location))))
+    }
+}
+
+/// This error is returned by [`FromAsarLabels::from_labels`] when one or more required labels were not found.
+#[derive(Debug, Clone)]
+pub struct MissingLabelsError {
+    /// The names of all the labels that were not found.
+    pub labels: Vec<String>,
+}
+
+impl fmt::Display for MissingLabelsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Missing labels: {}", self.labels.join(", "))
+    }
+}
+
+impl std::error::Error for MissingLabelsError {}
+
+/// This error is returned when the Asar library cannot be used.
+#[derive(Debug)]
+pub enum AsarError {
+    /// The shared library could not be loaded, or one of the `asar_*` symbols is missing from it.
+    #[cfg(feature = "dynamic")]
+    Load(libloading::Error),
+    /// The shared library was already loaded, it can only be loaded once.
+    #[cfg(feature = "dynamic")]
+    AlreadyLoaded,
+    /// The shared library could not be copied for a new [`AsarInstance`](dynamic::AsarInstance).
+    #[cfg(feature = "dynamic")]
+    Io(std::io::Error),
+    /// The API version of the library is not compatible with the one the bindings were generated for.
+    IncompatibleVersion { found: i32, expected: i32 },
+    /// The bindings were generated from a header whose `patchparams` has a different size than the one of Asar 1.91.
+    LayoutMismatch { found: usize, expected: usize },
+}
+
+impl fmt::Display for AsarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "dynamic")]
+            AsarError::Load(err) => write!(f, "Failed to load the Asar library: {}", err),
+            #[cfg(feature = "dynamic")]
+            AsarError::AlreadyLoaded => write!(f, "The Asar library is already loaded."),
+            #[cfg(feature = "dynamic")]
+            AsarError::Io(err) => write!(f, "Failed to copy the Asar library: {}", err),
+            AsarError::IncompatibleVersion { found, expected } => write!(
+                f,
+                "The Asar library has API version {}, which is not compatible with version {} of the bindings.",
+                found, expected
+            ),
+            AsarError::LayoutMismatch { found, expected } => write!(
+                f,
+                "The bindings have a `patchparams` of {} bytes instead of {}, they were generated from another version of Asar.",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AsarError {}
+
 impl RomData {
     /// Creates a new RomData with the data provided.
     pub fn from_vec(data: Vec<u8>) -> RomData {
@@ -328,6 +564,95 @@ impl ErrorData {
             errid: raw.errid,
         }
     }
+
+    /// Returns the id of the error, None if this is a warning or if the error is unknown.
+    ///
+    /// The id is taken from [`ErrorData::errid`], falling back to the `(E...)` tag of the message.
+    pub fn error_id(&self) -> Option<ErrorId> {
+        ErrorId::from_errid(self.errid).or_else(|| {
+            diagnostics::message_tag(self)
+                .and_then(|tag| tag.strip_prefix('E'))
+                .and_then(ErrorId::from_name)
+        })
+    }
+
+    /// Returns the id of the warning, None if this is not a warning or if the warning is unknown.
+    ///
+    /// The id is taken from [`ErrorData::errid`], falling back to the `(W...)` tag of the message.
+    pub fn warning_id(&self) -> Option<WarningId> {
+        WarningId::from_errid(self.errid).or_else(|| {
+            diagnostics::message_tag(self)
+                .and_then(|tag| tag.strip_prefix('W'))
+                .and_then(WarningId::from_name)
+        })
+    }
+
+    /// Returns the call stack of the error, starting with the location of the error itself, followed by the macro calls and
+    /// `incsrc`s that led to it, from the innermost to the outermost.
+    ///
+    /// The frames are parsed from the `(called from file:line: [block])` parts of [`ErrorData::fullerrdata`], if Asar didn't include
+    /// them, the only caller is taken from [`ErrorData::callerfilename`] and [`ErrorData::callerline`].
+    pub fn frames(&self) -> Vec<StackFrame> {
+        let mut frames = vec![StackFrame {
+            filename: self.filename.clone(),
+            line: self.line,
+            block: self.block.clone(),
+        }];
+
+        // only look at the location part, before the message, since the message may contain anything
+        let location = [": error: ", ": warning: "]
+            .iter()
+            .filter_map(|separator| self.fullerrdata.find(separator))
+            .min()
+            .map_or(self.fullerrdata.as_str(), |end| &self.fullerrdata[..end]);
+        let mut rest = location;
+        while let Some(start) = rest.find("(called from ") {
+            let frame = &rest[start + "(called from ".len()..];
+            // the block may contain parentheses, so the frame ends at the first ')' after it
+            let end = match (frame.find('['), frame.find(')')) {
+                (Some(open), Some(close)) if open < close => {
+                    frame[open..].find(']').and_then(|block_end| {
+                        frame[open + block_end..]
+                            .find(')')
+                            .map(|c| open + block_end + c)
+                    })
+                }
+                (_, close) => close,
+            };
+            let Some(end) = end else {
+                break;
+            };
+            frames.extend(StackFrame::parse(&frame[..end]));
+            rest = &frame[end + 1..];
+        }
+
+        if frames.len() == 1 && !self.callerfilename.is_empty() {
+            frames.push(StackFrame {
+                filename: self.callerfilename.clone(),
+                line: self.callerline,
+                block: String::new(),
+            });
+        }
+        frames
+    }
+}
+
+impl StackFrame {
+    /// Parses a `file:line` location, optionally followed by a `[block]`.
+    fn parse(frame: &str) -> Option<StackFrame> {
+        let (location, block) = match frame.find(" [") {
+            Some(open) if frame.ends_with(']') => {
+                (&frame[..open], &frame[open + 2..frame.len() - 1])
+            }
+            _ => (frame, ""),
+        };
+        let (filename, line) = location.trim_end_matches(':').rsplit_once(':')?;
+        Some(StackFrame {
+            filename: filename.to_string(),
+            line: line.trim().parse().ok()?,
+            block: block.to_string(),
+        })
+    }
 }
 
 impl Define {
@@ -392,6 +717,7 @@ impl AdvancedPatchOptions {
             memory_files: Vec::new(),
             override_checksum_gen: false,
             generate_checksum: false,
+            warning_policy: warnings::WarningPolicy::new(),
         }
     }
 
@@ -410,6 +736,10 @@ impl AdvancedPatchOptions {
             PatchOption::Warning(warnid, enabled) => {
                 self.warning_settings.push(WarnSetting { warnid, enabled })
             }
+            PatchOption::SetWarning(warning, enabled) => self.warning_settings.push(WarnSetting {
+                warnid: warning.to_string(),
+                enabled,
+            }),
             PatchOption::MemoryFile(filename, data) => {
                 self.memory_files.push(MemoryFile { filename, data })
             }
@@ -422,6 +752,7 @@ impl AdvancedPatchOptions {
                 self.generate_checksum = generate_checksum
             }
             PatchOption::ShouldReset(should_reset) => self.should_reset = should_reset,
+            PatchOption::WarningPolicy(policy) => self.warning_policy = policy,
         };
         self
     }
@@ -454,6 +785,8 @@ pub fn api_version() -> i32 {
 }
 
 /// Returns the version of Asar, in the format Major * 10000 + Minor * 100 + Revision.
+///
+/// See [`Version::current`] for the parsed version.
 pub fn version() -> i32 {
     unsafe { asar_version() }
 }
@@ -629,9 +962,17 @@ pub mod patching {
     ///
     /// Returns a [`PatchResult`] with the result of the patch operation.
     ///
+    /// If a warning is denied by the [`WarningPolicy`](warnings::WarningPolicy) of the options, the result is a [`PatchResult::Failure`]
+    /// with the denied warnings.
+    ///
     /// remarks: This function uses the global lock.
     #[use_asar_global_lock]
-    pub fn patch_ex<T: Into<String>>(rom: RomData, patch: T, options: AdvancedPatchOptions) -> PatchResult {
+    pub fn patch_ex<T: Into<String>>(
+        rom: RomData,
+        patch: T,
+        options: AdvancedPatchOptions,
+    ) -> PatchResult {
+        let policy = options.warning_policy.clone();
         let (romdata, result) = patch_ex_basic(rom, patch.into(), options);
 
         let mut count: c_int = 0;
@@ -640,7 +981,10 @@ pub mod patching {
         let warnings = warnings.iter().map(ErrorData::from_raw).collect();
 
         if result {
-            PatchResult::Success(romdata, warnings)
+            match policy.check(warnings) {
+                Ok(warnings) => PatchResult::Success(romdata, warnings),
+                Err(denied) => PatchResult::Failure(denied),
+            }
         } else {
             let mut count: c_int = 0;
             let errors = unsafe { asar_geterrors(&mut count) };
@@ -650,6 +994,29 @@ pub mod patching {
         }
     }
 
+    /// Patches the ROM data like [`patch_ex`], on the Asar thread of the [`actor`](crate::actor) module, without blocking
+    /// the calling thread.
+    ///
+    /// The operation is queued when the future is first polled, operations run one at a time in the order they were queued.
+    /// When the queue is full, the future waits for room before queueing the operation.
+    ///
+    /// The future doesn't depend on any async runtime. If the patch operation panics, the panic is resumed when the future is polled.
+    ///
+    /// remarks: This function uses the global lock on the Asar thread.
+    #[cfg(feature = "async")]
+    pub fn patch_ex_async<T: Into<String>>(
+        rom: RomData,
+        patch: T,
+        options: AdvancedPatchOptions,
+    ) -> impl std::future::Future<Output = PatchResult> + Send {
+        let patch = patch.into();
+        let job = crate::actor::AsarHandle::new().run_async(move || patch_ex(rom, patch, options));
+        async move {
+            job.await
+                .unwrap_or_else(|panicked| std::panic::resume_unwind(panicked.into_panic()))
+        }
+    }
+
     /// Returns the errors from the latest api call (usually [`patch`] or [`patch_ex`]).
     ///
     /// remarks: This function uses the global lock.
@@ -803,8 +1170,6 @@ pub mod patching {
         }
     }
 }
-#[cfg(feature = "thread-safe")]
-use parking_lot::ReentrantMutexGuard;
 
 /// The Patcher struct is a convenient wrapper around the [`patching`] api.
 ///
@@ -843,29 +1208,56 @@ pub struct Patcher {
 ///  
 /// - If there were any call to [`patching::patch`] or [`patching::patch_ex`] between the [`Patcher::apply`] call that returned this [`ApplyResult`] and this call,
 ///   this will return the warnings from the latest call instead of ones related to this [`ApplyResult`].   
-#[cfg(feature = "thread-safe")]
 pub struct ApplyResult<'a> {
     romdata: RomData,
     success: bool,
-    _guard: ReentrantMutexGuard<'a, ()>,
+    denied_warnings: Vec<WarningData>,
+    _alive: AliveFlag,
+    _lock: lock::Held<'a>,
 }
 
-/// This type represents the result of a patch operation.
+/// The result of [`AsarHandle::apply`](actor::AsarHandle::apply), with all the information about the patch operation
+/// gathered at once.
 ///
-/// It contains the possibly modified ROM data and a boolean indicating whether the patch was successful or not.
-///
-/// see [`ApplyResult::success`] for more information.
-#[cfg(not(feature = "thread-safe"))]
-pub struct ApplyResult<'a> {
-    romdata: RomData,
-    success: bool,
-    _marker: std::marker::PhantomData<&'a ()>,
+/// Unlike [`ApplyResult`], it doesn't hold the global lock, so it can be kept around and sent between threads.
+#[derive(Debug, Clone)]
+pub struct ApplyOutput {
+    /// The patched ROM, or the original one if the [`WarningPolicy`](warnings::WarningPolicy) denied a warning.
+    pub romdata: RomData,
+    pub success: bool,
+    pub warnings: Vec<WarningData>,
+    /// The errors of the patch operation, or the denied warnings if it failed because of the [`WarningPolicy`](warnings::WarningPolicy).
+    pub errors: Vec<ErrorData>,
+    pub prints: Vec<String>,
+    pub labels: Vec<Label>,
+    pub defines: Vec<Define>,
+    pub written_blocks: Vec<WrittenBlock>,
+    pub mapper_type: Option<MapperType>,
 }
 
 use std::sync::atomic::{AtomicBool, Ordering};
 
 static APPLYRESULT_ONCE_ALIVE: AtomicBool = AtomicBool::new(false);
 
+/// Marks the [`ApplyResult`] as alive, clearing [`APPLYRESULT_ONCE_ALIVE`] when dropped,
+/// including when [`Patcher::apply`] panics before returning it.
+struct AliveFlag;
+
+impl AliveFlag {
+    fn acquire() -> Result<AliveFlag, ConcurrentApplyError> {
+        APPLYRESULT_ONCE_ALIVE
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .map(|_| AliveFlag)
+            .map_err(|_| ConcurrentApplyError)
+    }
+}
+
+impl Drop for AliveFlag {
+    fn drop(&mut self) {
+        APPLYRESULT_ONCE_ALIVE.store(false, Ordering::SeqCst);
+    }
+}
+
 /// This error is returned when trying to call [`Patcher::apply`] while another [`ApplyResult`] is alive.
 ///
 /// This is to prevent multiple patch operations from happening at the same time, since Asar uses a lot of global state.
@@ -878,6 +1270,44 @@ impl fmt::Display for ConcurrentApplyError {
     }
 }
 
+/// This error is returned by [`Patcher::try_apply`] when the patch operation cannot start without waiting.
+#[derive(Debug)]
+pub enum TryApplyError {
+    /// Another [`ApplyResult`] is alive.
+    Concurrent(ConcurrentApplyError),
+    /// Another thread holds the global lock.
+    LockBusy(lock::LockBusyError),
+    /// The Asar library could not be loaded, or is not compatible with the bindings.
+    Asar(AsarError),
+}
+
+impl fmt::Display for TryApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryApplyError::Concurrent(err) => err.fmt(f),
+            TryApplyError::LockBusy(err) => err.fmt(f),
+            TryApplyError::Asar(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for TryApplyError {}
+
+impl From<ConcurrentApplyError> for TryApplyError {
+    fn from(err: ConcurrentApplyError) -> Self {
+        TryApplyError::Concurrent(err)
+    }
+}
+
+impl From<lock::TryLockError> for TryApplyError {
+    fn from(err: lock::TryLockError) -> Self {
+        match err {
+            lock::TryLockError::Busy(err) => TryApplyError::LockBusy(err),
+            lock::TryLockError::Asar(err) => TryApplyError::Asar(err),
+        }
+    }
+}
+
 impl Patcher {
     /// Creates a new Patcher with default options.
     pub fn new() -> Self {
@@ -897,57 +1327,117 @@ impl Patcher {
     ///
     /// See [`ConcurrentApplyError`] for more information.
     ///
+    /// Panics if the Asar library is not compatible with the bindings, [`Patcher::try_apply`] returns the error instead.
+    ///
     /// remarks: This function uses the global lock.
-    #[cfg(feature = "thread-safe")]
     pub fn apply<'a, T: Into<String>>(
         self,
         rom: RomData,
         patch: T,
     ) -> Result<ApplyResult<'a>, ConcurrentApplyError> {
-        if APPLYRESULT_ONCE_ALIVE
-            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
-            .is_err()
-        {
-            return Err(ConcurrentApplyError);
-        }
+        let alive = AliveFlag::acquire()?;
+        Ok(self.apply_locked(rom, patch.into(), alive, lock::acquire()))
+    }
 
-        let guard = global_asar_lock().lock();
-        let (romdata, result) =
-            patching::patch_ex_basic(rom, patch.into(), self.options.unwrap_or_default());
+    /// Applies the patch to the ROM data like [`Patcher::apply`], without waiting for the global lock.
+    ///
+    /// Returns [`TryApplyError::LockBusy`] if another thread holds the global lock, and [`TryApplyError::Asar`] if the Asar
+    /// library is not compatible with the bindings.
+    ///
+    /// remarks: This function uses the global lock.
+    pub fn try_apply<'a, T: Into<String>>(
+        self,
+        rom: RomData,
+        patch: T,
+    ) -> Result<ApplyResult<'a>, TryApplyError> {
+        let alive = AliveFlag::acquire()?;
+        let lock = lock::try_acquire(None)?;
+        Ok(self.apply_locked(rom, patch.into(), alive, lock))
+    }
 
-        Ok(ApplyResult {
-            romdata,
-            success: result,
-            _guard: guard,
+    fn apply_locked<'a>(
+        self,
+        rom: RomData,
+        patch: String,
+        alive: AliveFlag,
+        lock: lock::Held<'a>,
+    ) -> ApplyResult<'a> {
+        let options = self.options.unwrap_or_default();
+        let policy = options.warning_policy.clone();
+        let original = policy.can_deny().then(|| rom.clone());
+        let (romdata, result) = patching::patch_ex_basic(rom, patch, options);
+        let denied_warnings = if result {
+            policy.check(patching::warnings()).err().unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+
+        ApplyResult {
+            romdata: match original {
+                Some(original) if !denied_warnings.is_empty() => original,
+                _ => romdata,
+            },
+            success: result && denied_warnings.is_empty(),
+            denied_warnings,
+            _alive: alive,
+            _lock: lock,
+        }
+    }
+}
+
+impl Patcher {
+    /// Applies the patch and gathers all the information about the result, without resetting Asar.
+    pub(crate) fn apply_output(self, rom: RomData, patch: String) -> ApplyOutput {
+        crate::with_asar_lock(|| {
+            let options = self.options.unwrap_or_default();
+            let policy = options.warning_policy.clone();
+            let original = policy.can_deny().then(|| rom.clone());
+            let (romdata, result) = patching::patch_ex_basic(rom, patch, options);
+            let warnings = patching::warnings();
+            let denied_warnings = if result {
+                policy.check(warnings.clone()).err().unwrap_or_default()
+            } else {
+                Vec::new()
+            };
+            ApplyOutput {
+                romdata: match original {
+                    Some(original) if !denied_warnings.is_empty() => original,
+                    _ => romdata,
+                },
+                success: result && denied_warnings.is_empty(),
+                warnings,
+                errors: if denied_warnings.is_empty() {
+                    patching::errors()
+                } else {
+                    denied_warnings
+                },
+                prints: patching::prints(),
+                labels: patching::labels(),
+                defines: patching::defines(),
+                written_blocks: patching::written_blocks(),
+                mapper_type: patching::mapper_type(),
+            }
         })
     }
 
-    /// Applies the patch to the ROM data
+    /// Applies the patch to the ROM data on the Asar thread of the [`actor`] module, without blocking the calling thread.
     ///
-    /// Multiple patch operations cannot be done at the same time, this function will return an error if another [`ApplyResult`] is alive.
+    /// The information about the result is gathered on the Asar thread, then Asar is reset.
+    /// See [`patching::patch_ex_async`] for the ordering of the operations.
     ///
-    /// See [`ConcurrentApplyError`] for more information.
-    #[cfg(not(feature = "thread-safe"))]
-    pub fn apply<'a, T: Into<String>>(
+    /// remarks: This function uses the global lock on the Asar thread.
+    #[cfg(feature = "async")]
+    pub fn apply_async<T: Into<String>>(
         self,
         rom: RomData,
         patch: T,
-    ) -> Result<ApplyResult<'a>, ConcurrentApplyError> {
-        if APPLYRESULT_ONCE_ALIVE
-            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
-            .is_err()
-        {
-            return Err(ConcurrentApplyError);
+    ) -> impl std::future::Future<Output = ApplyOutput> + Send {
+        let patch = patch.into();
+        let job = actor::AsarHandle::new().run_async(move || self.apply_output(rom, patch));
+        async move {
+            job.await
+                .unwrap_or_else(|panicked| std::panic::resume_unwind(panicked.into_panic()))
         }
-
-        let (romdata, result) =
-            patching::patch_ex_basic(rom, patch.into(), self.options.unwrap_or_default());
-
-        Ok(ApplyResult {
-            romdata,
-            success: result,
-            _marker: std::marker::PhantomData,
-        })
     }
 }
 
@@ -964,15 +1454,19 @@ impl ApplyResult<'_> {
     }
 
     /// Returns the warnings from the apply operation.
-
     pub fn warnings(&self) -> Vec<WarningData> {
         patching::warnings()
     }
 
     /// Returns the errors from the apply operation.
     ///
+    /// If the patch failed because of the [`WarningPolicy`](warnings::WarningPolicy), the denied warnings are returned.
+    ///
     /// See the notes in the [`ApplyResult`] type for more information.
     pub fn errors(&self) -> Vec<ErrorData> {
+        if !self.denied_warnings.is_empty() {
+            return self.denied_warnings.clone();
+        }
         patching::errors()
     }
 
@@ -1032,13 +1526,13 @@ impl ApplyResult<'_> {
 
     /// Consumes the ApplyResult and returns the ROM data.
     ///
+    /// If the [`WarningPolicy`](warnings::WarningPolicy) denied a warning, this is the ROM as it was before the patch.
+    ///
     /// This will reset Asar, clearing all the errors, warnings and prints.
     ///
     /// Calling this method will allow another patch operation to be done with the [`Patcher::apply`] method.
     pub fn romdata(mut self) -> RomData {
-        let romdata = std::mem::take(&mut self.romdata);
-        APPLYRESULT_ONCE_ALIVE.store(false, Ordering::SeqCst);
-        romdata
+        std::mem::take(&mut self.romdata)
     }
 }
 
@@ -0,0 +1,299 @@
+//! The Asar global lock, see [`with_asar_lock`](crate::with_asar_lock), and metrics about its usage.
+//!
+//! [`metrics`] reports how many times the lock was taken, how long callers waited for it and how long they held it.
+//! Nested acquisitions by a thread that already holds the lock are not counted, and without the `thread-safe` feature
+//! the wait times are always zero.
+//!
+//! e.g.
+//! ```rust
+//! use std::time::Duration;
+//! use asar_snes::{lock, with_asar_lock_timeout};
+//!
+//! match with_asar_lock_timeout(Duration::from_millis(100), || asar_snes::patching::labels()) {
+//!     Ok(labels) => println!("{} labels", labels.len()),
+//!     Err(err) => println!("{}", err),
+//! }
+//! let metrics = lock::metrics();
+//! println!("waited {:?} on average", metrics.total_wait / metrics.acquisitions.max(1) as u32);
+//! ```
+use core::fmt;
+#[cfg(not(feature = "thread-safe"))]
+use std::marker::PhantomData;
+use std::{
+    cell::Cell,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "thread-safe")]
+use parking_lot::{ReentrantMutex, ReentrantMutexGuard};
+#[cfg(feature = "thread-safe")]
+use std::sync::OnceLock;
+
+use crate::{bindings::asar_reset, version, AsarError};
+
+#[cfg(feature = "thread-safe")]
+fn global_asar_lock() -> &'static ReentrantMutex<()> {
+    static LOCK: OnceLock<ReentrantMutex<()>> = OnceLock::new();
+    LOCK.get_or_init(|| ReentrantMutex::new(()))
+}
+
+#[cfg(not(feature = "thread-safe"))]
+fn global_asar_lock() -> &'static FakeLock {
+    &FakeLock
+}
+
+#[cfg(not(feature = "thread-safe"))]
+struct FakeLock;
+
+#[cfg(not(feature = "thread-safe"))]
+impl FakeLock {
+    fn lock(&self) -> FakeLock {
+        FakeLock
+    }
+
+    fn try_lock(&self) -> Option<FakeLock> {
+        Some(FakeLock)
+    }
+
+    fn try_lock_for(&self, _timeout: Duration) -> Option<FakeLock> {
+        Some(FakeLock)
+    }
+}
+
+#[cfg(feature = "thread-safe")]
+type Guard<'a> = ReentrantMutexGuard<'a, ()>;
+
+#[cfg(not(feature = "thread-safe"))]
+type Guard<'a> = (FakeLock, PhantomData<&'a ()>);
+
+/// Error returned when the Asar global lock could not be taken without blocking, or before the timeout.
+#[derive(Debug, Clone)]
+pub struct LockBusyError;
+
+impl fmt::Display for LockBusyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The Asar global lock is held by another thread.")
+    }
+}
+
+impl std::error::Error for LockBusyError {}
+
+/// Error returned by the functions taking the Asar global lock without blocking.
+#[derive(Debug)]
+pub enum TryLockError {
+    /// Another thread holds the lock.
+    Busy(LockBusyError),
+    /// The Asar library could not be loaded, or is not compatible with the bindings.
+    Asar(AsarError),
+}
+
+impl fmt::Display for TryLockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryLockError::Busy(err) => err.fmt(f),
+            TryLockError::Asar(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for TryLockError {}
+
+impl From<LockBusyError> for TryLockError {
+    fn from(err: LockBusyError) -> Self {
+        TryLockError::Busy(err)
+    }
+}
+
+impl From<AsarError> for TryLockError {
+    fn from(err: AsarError) -> Self {
+        TryLockError::Asar(err)
+    }
+}
+
+/// A snapshot of the usage of the Asar global lock, see [`metrics`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LockMetrics {
+    /// The number of times the lock was taken.
+    pub acquisitions: u64,
+    /// The number of acquisitions that had to wait for another thread.
+    pub contended: u64,
+    /// The number of times [`try_with_asar_lock`](crate::try_with_asar_lock) or
+    /// [`with_asar_lock_timeout`](crate::with_asar_lock_timeout) gave up.
+    pub busy: u64,
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+    pub total_hold: Duration,
+    pub max_hold: Duration,
+}
+
+struct Counters {
+    acquisitions: AtomicU64,
+    contended: AtomicU64,
+    busy: AtomicU64,
+    total_wait: AtomicU64,
+    max_wait: AtomicU64,
+    total_hold: AtomicU64,
+    max_hold: AtomicU64,
+}
+
+static COUNTERS: Counters = Counters {
+    acquisitions: AtomicU64::new(0),
+    contended: AtomicU64::new(0),
+    busy: AtomicU64::new(0),
+    total_wait: AtomicU64::new(0),
+    max_wait: AtomicU64::new(0),
+    total_hold: AtomicU64::new(0),
+    max_hold: AtomicU64::new(0),
+};
+
+thread_local! {
+    /// How many times the current thread holds the lock.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+/// Returns the usage of the lock since the start of the process, or since the last call to [`reset_metrics`].
+pub fn metrics() -> LockMetrics {
+    let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    LockMetrics {
+        acquisitions: load(&COUNTERS.acquisitions),
+        contended: load(&COUNTERS.contended),
+        busy: load(&COUNTERS.busy),
+        total_wait: Duration::from_nanos(load(&COUNTERS.total_wait)),
+        max_wait: Duration::from_nanos(load(&COUNTERS.max_wait)),
+        total_hold: Duration::from_nanos(load(&COUNTERS.total_hold)),
+        max_hold: Duration::from_nanos(load(&COUNTERS.max_hold)),
+    }
+}
+
+/// Resets the metrics returned by [`metrics`] to zero.
+pub fn reset_metrics() {
+    for counter in [
+        &COUNTERS.acquisitions,
+        &COUNTERS.contended,
+        &COUNTERS.busy,
+        &COUNTERS.total_wait,
+        &COUNTERS.max_wait,
+        &COUNTERS.total_hold,
+        &COUNTERS.max_hold,
+    ] {
+        counter.store(0, Ordering::Relaxed);
+    }
+}
+
+/// The global lock held by the current thread.
+///
+/// Resets Asar when dropped during a panic that started after its creation, so that a closure panicking under the lock
+/// doesn't leave its labels, defines and errors to the next caller, and records the metrics of the outermost acquisition.
+pub(crate) struct Held<'a> {
+    /// None while running an [`AsarInstance`](crate::dynamic::AsarInstance), which has its own state.
+    _guard: Option<Guard<'a>>,
+    panicking: bool,
+    /// When the lock was taken, None for nested acquisitions.
+    taken: Option<Instant>,
+}
+
+impl Held<'static> {
+    fn new(guard: Option<Guard<'static>>, start: Instant, contended: bool) -> Held<'static> {
+        let depth = DEPTH.get();
+        DEPTH.set(depth + 1);
+        let taken = (depth == 0).then(|| {
+            let now = Instant::now();
+            let wait = nanos(now - start);
+            COUNTERS.acquisitions.fetch_add(1, Ordering::Relaxed);
+            COUNTERS
+                .contended
+                .fetch_add(contended as u64, Ordering::Relaxed);
+            COUNTERS.total_wait.fetch_add(wait, Ordering::Relaxed);
+            COUNTERS.max_wait.fetch_max(wait, Ordering::Relaxed);
+            now
+        });
+        Held {
+            _guard: guard,
+            panicking: std::thread::panicking(),
+            taken,
+        }
+    }
+}
+
+impl Drop for Held<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() && !self.panicking && loaded() {
+            unsafe { asar_reset() };
+        }
+        DEPTH.set(DEPTH.get().saturating_sub(1));
+        if let Some(taken) = self.taken {
+            let hold = nanos(taken.elapsed());
+            COUNTERS.total_hold.fetch_add(hold, Ordering::Relaxed);
+            COUNTERS.max_hold.fetch_max(hold, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(feature = "thread-safe")]
+fn wrap(guard: ReentrantMutexGuard<'static, ()>) -> Option<Guard<'static>> {
+    Some(guard)
+}
+
+#[cfg(not(feature = "thread-safe"))]
+fn wrap(guard: FakeLock) -> Option<Guard<'static>> {
+    Some((guard, PhantomData))
+}
+
+/// Returns whether the current thread uses Asar without the global lock.
+fn unlocked() -> bool {
+    #[cfg(feature = "dynamic")]
+    return crate::dynamic::in_instance();
+    #[cfg(not(feature = "dynamic"))]
+    false
+}
+
+/// Returns whether the Asar library can be called, with the `dynamic` feature the panic may come from loading it.
+fn loaded() -> bool {
+    #[cfg(feature = "dynamic")]
+    return crate::dynamic::in_instance() || crate::dynamic::is_loaded();
+    #[cfg(not(feature = "dynamic"))]
+    true
+}
+
+/// Takes the lock, blocking until it is available.
+///
+/// Panics with the [`AsarError`] if the library is not compatible with the bindings, see [`version`].
+pub(crate) fn acquire() -> Held<'static> {
+    if let Err(err) = version::ensure_compatible() {
+        panic!("{}", err);
+    }
+    let start = Instant::now();
+    if unlocked() {
+        return Held::new(None, start, false);
+    }
+    let lock = global_asar_lock();
+    match lock.try_lock() {
+        Some(guard) => Held::new(wrap(guard), start, false),
+        None => Held::new(wrap(lock.lock()), start, true),
+    }
+}
+
+/// Takes the lock if it is available, waiting at most `timeout`, after checking that the library is compatible.
+pub(crate) fn try_acquire(timeout: Option<Duration>) -> Result<Held<'static>, TryLockError> {
+    version::ensure_compatible()?;
+    let start = Instant::now();
+    if unlocked() {
+        return Ok(Held::new(None, start, false));
+    }
+    let lock = global_asar_lock();
+    if let Some(guard) = lock.try_lock() {
+        return Ok(Held::new(wrap(guard), start, false));
+    }
+    match timeout.and_then(|timeout| lock.try_lock_for(timeout)) {
+        Some(guard) => Ok(Held::new(wrap(guard), start, true)),
+        None => {
+            COUNTERS.busy.fetch_add(1, Ordering::Relaxed);
+            Err(TryLockError::Busy(LockBusyError))
+        }
+    }
+}
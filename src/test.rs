@@ -5,6 +5,10 @@ use crate as asar;
 #[cfg(feature = "thread-safe")]
 use crate::{Patcher, RomData};
 
+/// Serializes the tests using [`Patcher::apply`], since only one [`crate::ApplyResult`] can be alive at a time.
+#[cfg(feature = "thread-safe")]
+static APPLY_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
 #[test]
 fn test_api_version() {
     let apiversion = asar::api_version();
@@ -45,10 +49,7 @@ endmacro"#;
         .option(PatchOption::Include("includefiles".into()))
         .option(PatchOption::Define("test".into(), "$18".into()))
         .option(PatchOption::Warning("Wrelative_path_used".into(), false))
-        .option(PatchOption::MemoryFile(
-            "test.asm".into(),
-            patchdata.into(),
-        ))
+        .option(PatchOption::MemoryFile("test.asm".into(), patchdata.into()))
         .option(PatchOption::MemoryFile(
             "includefiles/include.asm".into(),
             includedata.into(),
@@ -71,10 +72,8 @@ endmacro"#;
 fn test_get_labels() {
     let romdata = vec![].into();
     let patchdata = "org $008000\nlabel:";
-    let options = AdvancedPatchOptions::new().option(PatchOption::MemoryFile(
-        "test.asm".into(),
-        patchdata.into(),
-    ));
+    let options = AdvancedPatchOptions::new()
+        .option(PatchOption::MemoryFile("test.asm".into(), patchdata.into()));
     let (result, labels) = asar::with_asar_lock(|| {
         let result = asar::patching::patch_ex(romdata, "test.asm", options);
         let labels = asar::patching::labels();
@@ -97,9 +96,51 @@ fn test_proc_macro() {
     test();
 }
 
+#[test]
+fn test_proc_macro_signatures() {
+    use asar::use_asar_global_lock;
+
+    struct Wrapper {
+        value: i32,
+    }
+
+    impl Wrapper {
+        #[use_asar_global_lock]
+        fn value(&self) -> i32 {
+            self.value
+        }
+
+        #[use_asar_global_lock(crate = "crate")]
+        fn set_value<T>(&mut self, value: T)
+        where
+            T: Into<i32>,
+        {
+            self.value = value.into();
+        }
+
+        #[use_asar_global_lock]
+        unsafe fn raw_value(this: *const Wrapper) -> i32 {
+            (*this).value
+        }
+
+        #[use_asar_global_lock]
+        fn try_value(&self) -> Result<i32, String> {
+            let value: i32 = "1".parse().map_err(|_| String::from("invalid"))?;
+            Ok(self.value + value)
+        }
+    }
+
+    let mut wrapper = Wrapper { value: 1 };
+    wrapper.set_value(2u8);
+    assert_eq!(wrapper.value(), 2);
+    assert_eq!(unsafe { Wrapper::raw_value(&wrapper) }, 2);
+    assert_eq!(wrapper.try_value(), Ok(3));
+}
+
 #[test]
 #[cfg(feature = "thread-safe")]
 fn test_interface() {
+    let _apply = APPLY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
     let max_size = asar::max_rom_size() as usize;
     // we give the buffer "MAX_ROM_SIZE" bytes of space but tell asar that the ROM is 0 bytes long.
     // this is to test that asar will correctly resize the rom length to 5.
@@ -115,10 +156,7 @@ org $0D8000
     label:
     nop  
 "#;
-    patcher.option(PatchOption::MemoryFile(
-        "test.asm".into(),
-        patchdata.into(),
-    ));
+    patcher.option(PatchOption::MemoryFile("test.asm".into(), patchdata.into()));
     let patcher2 = patcher.clone();
     let patcher3 = patcher.clone();
 
@@ -152,3 +190,1056 @@ org $0D8000
     let result3 = patcher3.apply(romdata, "test2.asm");
     assert!(result3.is_ok());
 }
+
+#[test]
+fn test_derive_defines() {
+    use crate::{AsarDefines, DefineValue};
+
+    #[derive(DefineValue)]
+    enum Mode {
+        Normal,
+        #[asar(rename = "hard")]
+        Hard,
+    }
+
+    #[derive(AsarDefines)]
+    #[asar(prefix = "cfg_")]
+    struct Config {
+        #[asar(hex = 6)]
+        freespace: u32,
+        lives: u8,
+        #[asar(rename = "sa1")]
+        use_sa1: bool,
+        name: String,
+        mode: Mode,
+        other_mode: Mode,
+        missing: Option<u8>,
+        #[asar(skip)]
+        _comment: String,
+    }
+
+    let config = Config {
+        freespace: 0x108000,
+        lives: 5,
+        use_sa1: true,
+        name: "test".into(),
+        mode: Mode::Hard,
+        other_mode: Mode::Normal,
+        missing: None,
+        _comment: "not a define".into(),
+    };
+    let defines = config
+        .to_defines()
+        .into_iter()
+        .map(|option| match option {
+            PatchOption::Define(name, value) => (name, value),
+            _ => panic!("Expected a define"),
+        })
+        .collect::<Vec<_>>();
+    let expected = [
+        ("cfg_freespace", "$108000"),
+        ("cfg_lives", "5"),
+        ("cfg_sa1", "1"),
+        ("cfg_name", "test"),
+        ("cfg_mode", "hard"),
+        ("cfg_other_mode", "0"),
+    ];
+    assert_eq!(defines.len(), expected.len());
+    for ((name, value), (expected_name, expected_value)) in defines.iter().zip(expected) {
+        assert_eq!(name, expected_name);
+        assert_eq!(value, expected_value);
+    }
+}
+
+#[test]
+fn test_derive_from_labels() {
+    use crate::{FromAsarLabels, Label};
+
+    #[derive(FromAsarLabels)]
+    struct Addresses {
+        main: u32,
+        #[asar(label = "main_loop")]
+        game_loop: u32,
+        debug_hook: Option<u32>,
+        nmi: Option<u32>,
+    }
+
+    #[derive(FromAsarLabels)]
+    #[allow(dead_code)]
+    struct Missing {
+        main: u32,
+        irq: u32,
+        reset: u32,
+    }
+
+    let labels = vec![
+        Label {
+            name: "main".into(),
+            location: 0x008000,
+        },
+        Label {
+            name: "main_loop".into(),
+            location: 0x008010,
+        },
+        Label {
+            name: "nmi".into(),
+            location: 0x008100,
+        },
+    ];
+
+    let addresses = Addresses::from_labels(&labels).unwrap();
+    assert_eq!(addresses.main, 0x008000);
+    assert_eq!(addresses.game_loop, 0x008010);
+    assert_eq!(addresses.debug_hook, None);
+    assert_eq!(addresses.nmi, Some(0x008100));
+
+    let missing = Missing::from_labels(&labels);
+    assert!(missing.is_err());
+    assert_eq!(missing.err().unwrap().labels, ["irq", "reset"]);
+}
+
+#[test]
+#[cfg(feature = "thread-safe")]
+fn test_build() {
+    use crate::build::{Build, BuildPatch};
+
+    let dir = std::env::temp_dir().join("asar_snes_test_build");
+    std::fs::create_dir_all(dir.join("include")).unwrap();
+    std::fs::write(
+        dir.join("main.asm"),
+        "incsrc \"defs.asm\"\norg $008000\nmain:\n    lda #!value\n.loop:\n    bra .loop\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("include").join("defs.asm"), "!value = $01\n").unwrap();
+
+    let outputs = Build::new()
+        .include(dir.join("include"))
+        .patch_with(BuildPatch::new(dir.join("main.asm")).name("test_main"))
+        .out_dir(&dir)
+        .emit_rerun_if_changed(false)
+        .try_compile()
+        .unwrap();
+    assert_eq!(outputs.len(), 1);
+
+    let rom = std::fs::read(&outputs[0].rom_path).unwrap();
+    assert_eq!(rom[0..4], [0xA9, 0x01, 0x80, 0xFE]);
+    let labels = std::fs::read_to_string(&outputs[0].labels_path).unwrap();
+    assert!(labels.contains("pub const MAIN: u32 = 0x008000;"));
+    assert!(labels.contains("pub const MAIN_LOOP: u32 = 0x008002;"));
+}
+
+#[test]
+fn test_build_label_conflict() {
+    use crate::build::{labels_module, BuildError};
+    use crate::Label;
+    use std::path::Path;
+
+    let label = |name: &str, location| Label {
+        name: name.into(),
+        location,
+    };
+    let module = labels_module(Path::new("main.asm"), &[label("main", 0x8000)]).unwrap();
+    assert!(module.contains("pub const MAIN: u32 = 0x008000;"));
+
+    let labels = [label("main.loop", 0x8002), label("main_loop", 0x8004)];
+    match labels_module(Path::new("main.asm"), &labels) {
+        Err(BuildError::LabelConflict {
+            constant,
+            first,
+            second,
+            ..
+        }) => {
+            assert_eq!(constant, "MAIN_LOOP");
+            assert_eq!(
+                (first.as_str(), second.as_str()),
+                ("main.loop", "main_loop")
+            );
+        }
+        result => panic!("expected a label conflict, got {:?}", result),
+    }
+}
+
+#[test]
+fn test_deps_scan() {
+    use crate::deps::{self, DependencyKind};
+    use std::path::Path;
+
+    let main = r#"incsrc "lib/defs.asm" : incbin "gfx.bin":0-10
+!table = "font"
+table !table.tbl,rtl ; a comment
+incsrc "missing.asm"
+incsrc "!undefined/file.asm"
+incsrc "!dir/extra.asm""#;
+    // the define is set by an included file, before the line of main.asm using it
+    let defs = "incsrc ../shared.asm\nincsrc \"defs.asm\"\n!dir = lib";
+    let options = AdvancedPatchOptions::new()
+        .option(PatchOption::Include("common".into()))
+        .option(PatchOption::MemoryFile("main.asm".into(), main.into()))
+        .option(PatchOption::MemoryFile("lib/defs.asm".into(), defs.into()))
+        .option(PatchOption::MemoryFile("shared.asm".into(), "".into()))
+        .option(PatchOption::MemoryFile(
+            "common/gfx.bin".into(),
+            vec![0u8; 16].into(),
+        ))
+        .option(PatchOption::MemoryFile("font.tbl".into(), "00=A".into()))
+        .option(PatchOption::MemoryFile("lib/extra.asm".into(), "".into()));
+
+    let graph = deps::scan("main.asm", &options).unwrap();
+    let files = graph
+        .files()
+        .iter()
+        .map(|f| (f.path.to_str().unwrap(), f.kind))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        files,
+        [
+            ("main.asm", DependencyKind::Entry),
+            ("lib/defs.asm", DependencyKind::Source),
+            ("shared.asm", DependencyKind::Source),
+            ("common/gfx.bin", DependencyKind::Binary),
+            ("font.tbl", DependencyKind::Table),
+            ("lib/extra.asm", DependencyKind::Source),
+        ]
+    );
+    let unresolved = graph
+        .unresolved()
+        .map(|i| i.target.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(unresolved, ["missing.asm", "!undefined/file.asm"]);
+    assert_eq!(
+        graph
+            .dependencies_of(Path::new("lib/defs.asm"))
+            .collect::<Vec<_>>(),
+        [Path::new("shared.asm"), Path::new("lib/defs.asm")]
+    );
+
+    // memory files are not written to the depfile
+    let mut depfile = Vec::new();
+    graph
+        .write_depfile(&mut depfile, "out dir/main.sfc")
+        .unwrap();
+    assert_eq!(String::from_utf8(depfile).unwrap(), "out\\ dir/main.sfc:\n");
+}
+
+#[test]
+#[cfg(feature = "cache")]
+fn test_patch_cache() {
+    use crate::cache::{CachedPatchResult, PatchCache};
+
+    let dir = std::env::temp_dir().join("asar_snes_test_cache");
+    let cache = PatchCache::new(&dir).unwrap().max_entries(1);
+    cache.clear().unwrap();
+
+    let options = |value: &str| {
+        AdvancedPatchOptions::new()
+            .option(PatchOption::Define("value".into(), value.into()))
+            .option(PatchOption::MemoryFile(
+                "test.asm".into(),
+                "org $008000\nmain:\nlda #!value".into(),
+            ))
+    };
+    let apply = |value: &str| match cache.patch(vec![0; 4].into(), "test.asm", options(value)) {
+        CachedPatchResult::Success(patch) => patch,
+        CachedPatchResult::Failure(errors) => panic!("Expected success, got {:?}", errors),
+    };
+
+    let first = apply("$01");
+    assert!(!first.from_cache);
+    let second = apply("$01");
+    assert!(second.from_cache);
+    assert_eq!(second.romdata.data, first.romdata.data);
+    assert_eq!(second.romdata.length, first.romdata.length);
+    assert_eq!(second.labels[0].name, "main");
+    assert_eq!(second.written_blocks.len(), first.written_blocks.len());
+
+    // a different define is a different entry, which evicts the first one
+    let third = apply("$02");
+    assert!(!third.from_cache);
+    assert_eq!(third.romdata.data[0..2], [0xA9, 0x02]);
+    assert!(!apply("$01").from_cache);
+
+    // the define is set by the std defines file, so the included file is unknown until Asar reads it
+    let dir = std::env::temp_dir().join("asar_snes_test_cache_define");
+    std::fs::create_dir_all(&dir).unwrap();
+    let main = dir.join("main.asm");
+    let include = dir.join("value.asm");
+    let defines = dir.join("defines.txt");
+    std::fs::write(&main, "incsrc \"!file\"\norg $008000\nlda #!value\n").unwrap();
+    std::fs::write(&include, "!value = $01\n").unwrap();
+    std::fs::write(&defines, "file = value.asm\n").unwrap();
+    let apply = || {
+        let options = AdvancedPatchOptions::new().option(PatchOption::StdDefinesFile(
+            defines.to_string_lossy().into_owned(),
+        ));
+        match cache.patch(vec![0; 4].into(), main.to_string_lossy(), options) {
+            CachedPatchResult::Success(patch) => patch,
+            CachedPatchResult::Failure(errors) => panic!("Expected success, got {:?}", errors),
+        }
+    };
+    assert_eq!(apply().romdata.data[0..2], [0xA9, 0x01]);
+    std::fs::write(&include, "!value = $02\n").unwrap();
+    let edited = apply();
+    assert!(!edited.from_cache);
+    assert_eq!(edited.romdata.data[0..2], [0xA9, 0x02]);
+}
+
+#[test]
+#[cfg(feature = "thread-safe")]
+fn test_watcher() {
+    use crate::watch::Watcher;
+    use std::time::Duration;
+
+    let dir = std::env::temp_dir().join("asar_snes_test_watch");
+    std::fs::create_dir_all(&dir).unwrap();
+    let main = dir.join("main.asm");
+    let include = dir.join("value.asm");
+    std::fs::write(&main, "incsrc \"value.asm\"\norg $008000\nlda #!value\n").unwrap();
+    std::fs::write(&include, "!value = $01\n").unwrap();
+
+    let (handle, events) = Watcher::new(Patcher::new(), vec![0; 4].into(), main.to_string_lossy())
+        .interval(Duration::from_millis(10))
+        .debounce(Duration::from_millis(10))
+        .spawn();
+
+    let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(event.changed.is_empty());
+    match event.result {
+        PatchResult::Success(rom, _) => assert_eq!(rom.data[0..2], [0xA9, 0x01]),
+        PatchResult::Failure(errors) => panic!("Expected success, got {:?}", errors),
+    }
+
+    // make sure the modification time changes even on filesystems with coarse timestamps
+    std::thread::sleep(Duration::from_millis(1100));
+    std::fs::write(&include, "!value = $02\n").unwrap();
+    let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(event.changed, [include]);
+    match event.result {
+        PatchResult::Success(rom, _) => assert_eq!(rom.data[0..2], [0xA9, 0x02]),
+        PatchResult::Failure(errors) => panic!("Expected success, got {:?}", errors),
+    }
+
+    handle.stop();
+}
+
+#[test]
+fn test_render_diagnostics() {
+    use crate::diagnostics::{ColorChoice, Renderer, Severity};
+    use crate::{ErrorData, ErrorId};
+
+    let options = AdvancedPatchOptions::new().option(PatchOption::MemoryFile(
+        "main.asm".into(),
+        "org $008000\n\tfoo bar\nrtl\n".into(),
+    ));
+    let error = ErrorData {
+        fullerrdata: "main.asm:2: error: (Eunknown_command): Unknown command. [foo bar]".into(),
+        rawerrdata: "(Eunknown_command): Unknown command.".into(),
+        block: "foo bar".into(),
+        filename: "main.asm".into(),
+        line: 2,
+        callerfilename: "macros.asm".into(),
+        callerline: 10,
+        errid: ErrorId::UnknownCommand.errid(),
+    };
+    let renderer = Renderer::new()
+        .memory_files(&options)
+        .color(ColorChoice::Never);
+    assert_eq!(
+        renderer.render(&error, Severity::Error),
+        "error[Eunknown_command]: Unknown command.\n \
+         --> main.asm:2\n  \
+          |\n\
+         2 |     foo bar\n  \
+          |     ^^^^^^^\n  \
+          = note: called from macros.asm:10\n"
+    );
+
+    // without a source, only the location is shown
+    let warning = ErrorData {
+        rawerrdata: "(Wrelative_path_used): Relative path used.".into(),
+        filename: "missing.asm".into(),
+        callerfilename: "".into(),
+        ..error
+    };
+    assert_eq!(
+        renderer.render(&warning, Severity::Warning),
+        "warning[Wrelative_path_used]: Relative path used.\n --> missing.asm:2\n"
+    );
+
+    // without a tag, the id comes from the errid
+    let untagged = ErrorData {
+        fullerrdata: "main.asm:2: error: Unknown command. [foo bar]".into(),
+        rawerrdata: "Unknown command.".into(),
+        filename: "".into(),
+        ..warning
+    };
+    assert_eq!(
+        renderer.render(&untagged, Severity::Error),
+        "error[Eunknown_command]: Unknown command.\n"
+    );
+    let unknown = ErrorData {
+        errid: 0,
+        ..untagged
+    };
+    assert_eq!(
+        renderer.render(&unknown, Severity::Error),
+        "error: Unknown command.\n"
+    );
+}
+
+#[test]
+fn test_error_frames() {
+    use crate::{ErrorData, ErrorId, StackFrame};
+
+    let frame = |filename: &str, line, block: &str| StackFrame {
+        filename: filename.into(),
+        line,
+        block: block.into(),
+    };
+    let error = ErrorData {
+        fullerrdata: "lib/inner.asm:4 (called from lib/outer.asm:9: [%inner(1)]) (called from main.asm:2: [%outer()]): error: (Eunknown_command): Unknown command. [foo]".into(),
+        rawerrdata: "(Eunknown_command): Unknown command.".into(),
+        block: "foo".into(),
+        filename: "lib/inner.asm".into(),
+        line: 4,
+        callerfilename: "lib/outer.asm".into(),
+        callerline: 9,
+        errid: ErrorId::UnknownCommand.errid(),
+    };
+    assert_eq!(
+        error.frames(),
+        [
+            frame("lib/inner.asm", 4, "foo"),
+            frame("lib/outer.asm", 9, "%inner(1)"),
+            frame("main.asm", 2, "%outer()"),
+        ]
+    );
+
+    // without a call stack in the message, the caller is used
+    let error = ErrorData {
+        fullerrdata: "lib/inner.asm:4: error: (Eunknown_command): Unknown command. [foo]".into(),
+        ..error
+    };
+    assert_eq!(
+        error.frames(),
+        [
+            frame("lib/inner.asm", 4, "foo"),
+            frame("lib/outer.asm", 9, "")
+        ]
+    );
+}
+
+/// Returns the names of the `<prefix>*` enumerators of a header of the Asar submodule, in declaration order, None if the
+/// submodule is not checked out.
+fn header_ids(header: &str, prefix: &str) -> Option<Vec<String>> {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/asar/src/asar")
+        .join(header);
+    let contents = std::fs::read_to_string(path).ok()?;
+    let mut names = Vec::new();
+    for line in contents.lines() {
+        let line = line.split("//").next().unwrap_or_default().trim();
+        let Some(rest) = line.strip_prefix(prefix) else {
+            continue;
+        };
+        let name = rest
+            .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .next()
+            .unwrap_or_default();
+        if !["start", "end", "count"].contains(&name) && !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    Some(names)
+}
+
+#[test]
+fn test_warning_ids_match_header() {
+    use crate::WarningId;
+
+    let Some(names) = header_ids("warnings.h", "warning_id_") else {
+        eprintln!("the Asar submodule is not checked out, skipping");
+        return;
+    };
+    let catalog = WarningId::ALL
+        .iter()
+        .map(|w| w.name().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(catalog, names);
+    for (index, warning) in WarningId::ALL.iter().enumerate() {
+        assert_eq!(warning.errid(), 1001 + index as i32);
+    }
+}
+
+#[test]
+fn test_warning_ids() {
+    use crate::{ErrorData, WarningId};
+
+    for &warning in WarningId::ALL {
+        assert_eq!(WarningId::from_errid(warning.errid()), Some(warning));
+        assert_eq!(WarningId::from_name(&warning.to_string()), Some(warning));
+    }
+    assert_eq!(
+        WarningId::from_name("relative_path_used"),
+        Some(WarningId::RelativePathUsed)
+    );
+    assert_eq!(
+        WarningId::from_name("W1001"),
+        Some(WarningId::RelativePathUsed)
+    );
+    assert_eq!(WarningId::from_name("Wrelative_path_usde"), None);
+
+    let options = AdvancedPatchOptions::new()
+        .option(PatchOption::SetWarning(WarningId::RelativePathUsed, false));
+    assert_eq!(options.warning_settings[0].warnid, "Wrelative_path_used");
+
+    let warning = ErrorData {
+        fullerrdata: "main.asm:1: warning: (Wwarn_command): warn command: hi [warn \"hi\"]".into(),
+        rawerrdata: "(Wwarn_command): warn command: hi".into(),
+        block: "warn \"hi\"".into(),
+        filename: "main.asm".into(),
+        line: 1,
+        callerfilename: "".into(),
+        callerline: -1,
+        errid: 0,
+    };
+    assert_eq!(warning.warning_id(), Some(WarningId::WarnCommand));
+    let warning = ErrorData {
+        rawerrdata: "warn command: hi".into(),
+        errid: 1009,
+        ..warning
+    };
+    assert_eq!(warning.warning_id(), Some(WarningId::WarnCommand));
+    // without a known errid, the tag is also looked for in the full message
+    let warning = ErrorData {
+        errid: 0,
+        ..warning
+    };
+    assert_eq!(warning.warning_id(), Some(WarningId::WarnCommand));
+    let warning = ErrorData {
+        fullerrdata: "main.asm:1: warning: warn command: hi [warn \"hi\"]".into(),
+        ..warning
+    };
+    assert_eq!(warning.warning_id(), None);
+}
+
+#[test]
+fn test_error_ids() {
+    use crate::{ErrorCategory, ErrorData, ErrorId, WarningId};
+
+    for &error in ErrorId::ALL {
+        assert_eq!(ErrorId::from_errid(error.errid()), Some(error));
+        assert_eq!(ErrorId::from_name(&error.to_string()), Some(error));
+        assert!(!error.explain().is_empty());
+    }
+    assert_eq!(ErrorId::UnknownCommand.category(), ErrorCategory::Syntax);
+    assert_eq!(ErrorId::from_name("Eunknown_comand"), None);
+    assert_eq!(ErrorId::from_name("E5001"), Some(ErrorId::LimitReached));
+
+    let error = ErrorData {
+        fullerrdata: "main.asm:1: error: (Eunknown_command): Unknown command. [foo]".into(),
+        rawerrdata: "(Eunknown_command): Unknown command.".into(),
+        block: "foo".into(),
+        filename: "main.asm".into(),
+        line: 1,
+        callerfilename: "".into(),
+        callerline: -1,
+        errid: 0,
+    };
+    assert_eq!(error.error_id(), Some(ErrorId::UnknownCommand));
+    assert_eq!(error.warning_id(), None);
+    let warning = ErrorData {
+        rawerrdata: "(Wwarn_command): warn command: hi".into(),
+        ..error
+    };
+    assert_eq!(warning.error_id(), None);
+    assert_eq!(warning.warning_id(), Some(WarningId::WarnCommand));
+    let error = ErrorData {
+        rawerrdata: "Unknown command.".into(),
+        errid: 5033,
+        ..warning
+    };
+    assert_eq!(error.error_id(), Some(ErrorId::UnknownCommand));
+    // without a known errid, the tag is also looked for in the full message
+    let error = ErrorData { errid: 0, ..error };
+    assert_eq!(error.error_id(), Some(ErrorId::UnknownCommand));
+}
+
+#[test]
+fn test_error_ids_match_header() {
+    use crate::ErrorId;
+
+    let Some(names) = header_ids("errors.h", "error_id_") else {
+        eprintln!("the Asar submodule is not checked out, skipping");
+        return;
+    };
+    let catalog = ErrorId::ALL
+        .iter()
+        .map(|e| e.name().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(catalog, names);
+    for (index, error) in ErrorId::ALL.iter().enumerate() {
+        assert_eq!(error.errid(), 5001 + index as i32);
+    }
+}
+
+#[test]
+fn test_warning_policy() {
+    use crate::warnings::WarningPolicy;
+    use crate::{ErrorData, WarningId};
+
+    let warning = |id: WarningId, filename: &str| ErrorData {
+        fullerrdata: format!("{}:1: warning: ({}): message [block]", filename, id),
+        rawerrdata: format!("({}): message", id),
+        block: "block".into(),
+        filename: filename.into(),
+        line: 1,
+        callerfilename: "".into(),
+        callerline: -1,
+        errid: id.errid(),
+    };
+    let deprecated = warning(WarningId::FeatureDeprecated, "asm/main.asm");
+    let vendor_deprecated = warning(WarningId::FeatureDeprecated, "asm/vendor/lib.asm");
+    let warn = warning(WarningId::WarnCommand, "asm/main.asm");
+    let vendor_warn = warning(WarningId::WarnCommand, "asm/vendor/lib.asm");
+
+    let policy = WarningPolicy::new();
+    assert!(!policy.is_denied(&deprecated));
+
+    let policy = WarningPolicy::new().deny(WarningId::FeatureDeprecated);
+    assert!(policy.is_denied(&deprecated));
+    assert!(!policy.is_denied(&warn));
+
+    let policy = WarningPolicy::new()
+        .deny_all()
+        .allow(WarningId::WarnCommand)
+        .allow_in("asm/vendor", WarningId::FeatureDeprecated);
+    assert!(policy.is_denied(&deprecated));
+    assert!(!policy.is_denied(&vendor_deprecated));
+    assert!(!policy.is_denied(&warn));
+    assert!(!policy.is_denied(&vendor_warn));
+
+    let policy = WarningPolicy::new().deny_all().allow_path("./asm/vendor/");
+    assert!(policy.is_denied(&warn));
+    assert!(!policy.is_denied(&vendor_warn));
+
+    // Only the errid identifies the warning.
+    let untagged = ErrorData {
+        fullerrdata: "asm/main.asm:1: warning: message [block]".into(),
+        rawerrdata: "message".into(),
+        ..warning(WarningId::WarnCommand, "asm/main.asm")
+    };
+    let policy = WarningPolicy::new().deny(WarningId::WarnCommand);
+    assert!(policy.is_denied(&untagged));
+    let policy = WarningPolicy::new()
+        .deny_all()
+        .allow(WarningId::WarnCommand);
+    assert!(!policy.is_denied(&untagged));
+}
+
+#[test]
+#[cfg(feature = "thread-safe")]
+fn test_warning_policy_patch() {
+    use crate::warnings::WarningPolicy;
+    use crate::WarningId;
+
+    let patch = "org $008000\nwarn \"careful\"\nlda #$00\n";
+    let options = AdvancedPatchOptions::new()
+        .option(PatchOption::MemoryFile("test.asm".into(), patch.into()))
+        .option(PatchOption::WarningPolicy(
+            WarningPolicy::new().deny(WarningId::WarnCommand),
+        ));
+    match asar::patching::patch_ex(vec![0; 0x8000].into(), "test.asm", options) {
+        PatchResult::Failure(errors) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].warning_id(), Some(WarningId::WarnCommand));
+        }
+        PatchResult::Success(_, _) => panic!("the warning should have been denied"),
+    }
+
+    let mut patcher = Patcher::new();
+    patcher.options(
+        AdvancedPatchOptions::new()
+            .option(PatchOption::MemoryFile("test.asm".into(), patch.into()))
+            .option(PatchOption::WarningPolicy(
+                WarningPolicy::new().deny(WarningId::WarnCommand),
+            )),
+    );
+    let result = patcher.apply(vec![0; 0x8000].into(), "test.asm").unwrap();
+    assert!(!result.success());
+    assert_eq!(result.romdata().data, vec![0; 0x8000]);
+}
+
+#[test]
+#[cfg(feature = "thread-safe")]
+fn test_worker_protocol() {
+    use crate::codec::Decoder;
+    use crate::worker::{encode_job, read_result, serve, WorkerPatchResult};
+
+    let rom: RomData = vec![0; 0x8000].into();
+    let mut input = Vec::new();
+    let patch = "org $008000\ntest:\nlda #$42\nprint \"done\"\n";
+    let options = AdvancedPatchOptions::new()
+        .option(PatchOption::MemoryFile("test.asm".into(), patch.into()))
+        .option(PatchOption::Define("value".into(), "1".into()));
+    input.extend(encode_job(&rom, "test.asm", &options).unwrap());
+    let options = AdvancedPatchOptions::new().option(PatchOption::MemoryFile(
+        "bad.asm".into(),
+        "foo bar\n".into(),
+    ));
+    input.extend(encode_job(&rom, "bad.asm", &options).unwrap());
+
+    let mut output = Vec::new();
+    serve(input.as_slice(), &mut output).unwrap();
+
+    let mut decoder = Decoder::new(output.as_slice());
+    assert_eq!(decoder.u32().unwrap(), u32::from_le_bytes(*b"ASRW"));
+    assert_eq!(decoder.u32().unwrap(), 1);
+    match read_result(&mut decoder, rom.clone()).unwrap() {
+        WorkerPatchResult::Success(patch) => {
+            assert_eq!(patch.romdata.data.len(), 0x8000);
+            assert_eq!(&patch.romdata.data[..2], &[0xA9, 0x42]);
+            assert_eq!(patch.labels[0].name, "test");
+            assert_eq!(patch.prints, ["done"]);
+            assert_eq!(patch.written_blocks.len(), 1);
+        }
+        WorkerPatchResult::Failure(errors) => panic!("{:?}", errors),
+    }
+    match read_result(&mut decoder, rom).unwrap() {
+        WorkerPatchResult::Failure(errors) => assert!(!errors.is_empty()),
+        WorkerPatchResult::Success(_) => panic!("the patch should have failed"),
+    }
+}
+
+#[test]
+fn test_worker_pool_missing_program() {
+    use crate::worker::{WorkerError, WorkerPool};
+
+    let pool = WorkerPool::new(2).program("/nonexistent/asar-worker");
+    assert_eq!(pool.size(), 2);
+    let results = pool.patch_all((0..3).map(|_| {
+        (
+            vec![0; 0x8000].into(),
+            "test.asm".into(),
+            AdvancedPatchOptions::new(),
+        )
+    }));
+    assert_eq!(results.len(), 3);
+    assert!(results
+        .iter()
+        .all(|result| matches!(result, Err(WorkerError::Io(_)))));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_worker_timeout() {
+    use crate::worker::{CancellationToken, WorkerError, WorkerPool};
+    use std::time::{Duration, Instant};
+
+    // fake workers that complete the handshake, then hang or exit
+    let fake_worker = |script: &str| {
+        WorkerPool::new(1)
+            .program("sh")
+            .arg("-c")
+            .arg(format!(r"printf 'ASRW\001\000\000\000'; {}", script))
+    };
+    let rom = || vec![0; 0x8000].into();
+
+    let pool = fake_worker("exec sleep 10").timeout(Duration::from_millis(200));
+    let start = Instant::now();
+    let result = pool.patch(rom(), "test.asm", AdvancedPatchOptions::new());
+    assert!(matches!(result, Err(WorkerError::Timeout(_))));
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    let pool = fake_worker("exec sleep 10");
+    let cancel = CancellationToken::new();
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            std::thread::sleep(Duration::from_millis(200));
+            cancel.cancel();
+        });
+        let result =
+            pool.patch_cancellable(rom(), "test.asm", AdvancedPatchOptions::new(), &cancel);
+        assert!(matches!(result, Err(WorkerError::Cancelled)));
+    });
+    assert!(start.elapsed() < Duration::from_secs(5));
+    let result = pool.patch_cancellable(rom(), "test.asm", AdvancedPatchOptions::new(), &cancel);
+    assert!(matches!(result, Err(WorkerError::Cancelled)));
+
+    let pool = fake_worker("exit 3");
+    let result = pool.patch(rom(), "test.asm", AdvancedPatchOptions::new());
+    assert!(matches!(result, Err(WorkerError::Crashed(status)) if status.code() == Some(3)));
+
+    // a worker that never completes the handshake
+    let silent_worker = || {
+        WorkerPool::new(1)
+            .program("sh")
+            .arg("-c")
+            .arg("exec sleep 10")
+    };
+    let start = Instant::now();
+    let pool = silent_worker().timeout(Duration::from_millis(200));
+    let result = pool.patch(rom(), "test.asm", AdvancedPatchOptions::new());
+    assert!(matches!(result, Err(WorkerError::Timeout(_))));
+    let pool = silent_worker();
+    let cancel = CancellationToken::new();
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            std::thread::sleep(Duration::from_millis(200));
+            cancel.cancel();
+        });
+        let result =
+            pool.patch_cancellable(rom(), "test.asm", AdvancedPatchOptions::new(), &cancel);
+        assert!(matches!(result, Err(WorkerError::Cancelled)));
+    });
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_async_queue() {
+    use crate::actor::{block_on, AsarHandle, QUEUE_CAPACITY};
+    use std::future::Future;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Waker};
+
+    // queue more jobs than the queue can hold, the extra ones wait for room
+    let asar = AsarHandle::new();
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let mut futures = (0..QUEUE_CAPACITY * 3)
+        .map(|i| {
+            let order = Arc::clone(&order);
+            Box::pin(asar.run_async(move || {
+                order.lock().unwrap().push(i);
+                i
+            }))
+        })
+        .collect::<Vec<_>>();
+    let mut cx = Context::from_waker(Waker::noop());
+    for future in &mut futures {
+        let _ = future.as_mut().poll(&mut cx);
+    }
+    for (i, future) in futures.into_iter().enumerate() {
+        assert_eq!(block_on(future).unwrap(), i);
+    }
+    assert_eq!(
+        *order.lock().unwrap(),
+        (0..QUEUE_CAPACITY * 3).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_asar_handle() {
+    use crate::actor::AsarHandle;
+
+    let asar = AsarHandle::new();
+    let threads = (0..4)
+        .map(|i| {
+            let asar = asar.clone();
+            std::thread::spawn(move || asar.run(move || i * 2).unwrap())
+        })
+        .collect::<Vec<_>>();
+    let values = threads
+        .into_iter()
+        .map(|thread| thread.join().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(values, [0, 2, 4, 6]);
+
+    // a panicking job fails without stopping the Asar thread
+    let panicked = asar.run(|| panic!("job failed")).unwrap_err();
+    assert_eq!(panicked.message(), Some("job failed"));
+    assert_eq!(asar.run(|| 42).unwrap(), 42);
+
+    // jobs can run other jobs
+    let nested = asar.clone();
+    assert_eq!(asar.run(move || nested.run(|| 1).unwrap() + 1).unwrap(), 2);
+}
+
+#[test]
+fn test_asar_handle_reset() {
+    use crate::actor::AsarHandle;
+
+    let asar = AsarHandle::new();
+    let patch = "org $008000\nmain:\nlda #$42\n";
+    let options = AdvancedPatchOptions::new()
+        .option(PatchOption::MemoryFile("test.asm".into(), patch.into()));
+    let mut patcher = asar::Patcher::new();
+    patcher.options(options);
+    let output = asar
+        .apply(patcher, vec![0; 0x8000].into(), "test.asm")
+        .unwrap();
+    assert!(output.success);
+    assert_eq!(output.labels[0].name, "main");
+
+    // Asar is reset after each job
+    assert!(asar.run(asar::patching::labels).unwrap().is_empty());
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_patch_ex_async() {
+    let patch = "org $008000\nmain:\nlda #$42\n";
+    let options = AdvancedPatchOptions::new()
+        .option(PatchOption::MemoryFile("test.asm".into(), patch.into()));
+    use crate::actor::block_on;
+
+    let result = block_on(asar::patching::patch_ex_async(
+        vec![0; 0x8000].into(),
+        "test.asm",
+        options.clone(),
+    ));
+    match result {
+        PatchResult::Success(rom, _) => assert_eq!(&rom.data[..2], &[0xA9, 0x42]),
+        PatchResult::Failure(errors) => panic!("{:?}", errors),
+    }
+
+    let mut patcher = Patcher::new();
+    patcher.options(options);
+    let result = block_on(patcher.apply_async(vec![0; 0x8000].into(), "test.asm"));
+    assert!(result.success);
+    assert_eq!(result.labels[0].name, "main");
+    assert_eq!(&result.romdata.data[..2], &[0xA9, 0x42]);
+}
+
+#[test]
+#[cfg(feature = "thread-safe")]
+fn test_panic_resets_asar() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let _apply = APPLY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let patch = "org $008000\nmain:\n!value = 1\nlda #$42\n";
+    let options = AdvancedPatchOptions::new()
+        .option(PatchOption::MemoryFile("test.asm".into(), patch.into()));
+
+    // a panic between patch_ex and reading the results
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        asar::with_asar_lock(|| {
+            let result =
+                asar::patching::patch_ex(vec![0; 0x8000].into(), "test.asm", options.clone());
+            assert!(matches!(result, PatchResult::Success(_, _)));
+            panic!("interrupted");
+        })
+    }));
+    assert!(result.is_err());
+    assert!(asar::patching::labels().is_empty());
+    assert_eq!(asar::patching::define("value"), None);
+
+    // a panic while an ApplyResult is alive
+    let mut patcher = Patcher::new();
+    patcher.options(options);
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let result = patcher
+            .clone()
+            .apply(vec![0; 0x8000].into(), "test.asm")
+            .unwrap();
+        assert!(result.success());
+        panic!("interrupted");
+    }));
+    assert!(result.is_err());
+    assert!(asar::patching::labels().is_empty());
+
+    // a panic inside Patcher::apply, the patch path can't contain a nul byte
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let _ = patcher.clone().apply(vec![0; 0x8000].into(), "test\0.asm");
+    }));
+    assert!(result.is_err());
+
+    // the next apply is not rejected with ConcurrentApplyError
+    let result = patcher.apply(vec![0; 0x8000].into(), "test.asm").unwrap();
+    assert!(result.success());
+    assert_eq!(result.labels().len(), 1);
+}
+
+#[test]
+#[cfg(feature = "thread-safe")]
+fn test_lock_try_and_timeout() {
+    use crate::{lock, try_with_asar_lock, with_asar_lock_timeout, TryApplyError};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let _apply = APPLY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let before = lock::metrics();
+
+    // the lock is recursive, so trying it while holding it succeeds
+    assert_eq!(
+        asar::with_asar_lock(|| try_with_asar_lock(|| 1)).unwrap(),
+        1
+    );
+
+    let (locked, locked_receiver) = mpsc::channel();
+    let (release, release_receiver) = mpsc::channel::<()>();
+    let holder = std::thread::spawn(move || {
+        asar::with_asar_lock(|| {
+            locked.send(()).unwrap();
+            let _ = release_receiver.recv();
+        })
+    });
+    locked_receiver.recv().unwrap();
+
+    assert!(try_with_asar_lock(|| ()).is_err());
+    assert!(with_asar_lock_timeout(Duration::from_millis(50), || ()).is_err());
+    let result = Patcher::new().try_apply(vec![0; 0x8000].into(), "test.asm");
+    assert!(matches!(result, Err(TryApplyError::LockBusy(_))));
+
+    // the waiting thread gets the lock once it is released
+    let waiter = std::thread::spawn(|| with_asar_lock_timeout(Duration::from_secs(10), || 2));
+    std::thread::sleep(Duration::from_millis(50));
+    release.send(()).unwrap();
+    holder.join().unwrap();
+    assert_eq!(waiter.join().unwrap().unwrap(), 2);
+
+    let after = lock::metrics();
+    assert!(after.acquisitions >= before.acquisitions + 3);
+    assert!(after.busy >= before.busy + 3);
+    assert!(after.contended > before.contended);
+    assert!(after.max_hold >= Duration::from_millis(50));
+    assert!(after.max_wait >= Duration::from_millis(50));
+}
+
+#[test]
+#[cfg(feature = "dynamic")]
+fn test_dynamic_load_missing_library() {
+    let result = asar::dynamic::load("/nonexistent/libasar.so");
+    assert!(matches!(result, Err(asar::AsarError::Load(_))));
+    assert!(!asar::dynamic::is_loaded());
+}
+
+#[test]
+#[cfg(feature = "dynamic")]
+fn test_asar_instance_missing_library() {
+    let result = asar::dynamic::AsarInstance::from_path("/nonexistent/libasar.so");
+    assert!(matches!(result, Err(asar::AsarError::Io(_))));
+}
+
+#[test]
+fn test_version_compatibility() {
+    use crate::version::{self, Version};
+
+    let parsed = Version::from_raw(10901);
+    assert_eq!(
+        parsed,
+        Version {
+            major: 1,
+            minor: 9,
+            patch: 1
+        }
+    );
+    assert_eq!(parsed.to_string(), "1.9.1");
+    assert_eq!(parsed.asar_string(), "1.91");
+    assert_eq!(Version::from_raw(11001).asar_string(), "1.10.1");
+    assert_eq!(Version::from_raw(10912).asar_string(), "1.9.12");
+    assert_eq!(parsed.to_raw(), 10901);
+    assert!(Version::from_raw(20000) > version::VERSION);
+
+    assert!(version::check_api_version(303).is_ok());
+    assert!(version::check_api_version(399).is_ok());
+    assert!(matches!(
+        version::check_api_version(302),
+        Err(asar::AsarError::IncompatibleVersion {
+            found: 302,
+            expected: 303
+        })
+    ));
+    assert!(version::check_api_version(400).is_err());
+    assert!(version::check().is_ok());
+    assert_eq!(Version::current(), version::VERSION);
+}
@@ -0,0 +1,126 @@
+//! The version of Asar, and the checks that the Asar library is compatible with the bindings.
+//!
+//! The bindings are generated from the header of Asar 1.91. The first time the global lock is taken, the API version of
+//! the library and the size of `patchparams` are compared with the ones the bindings were generated for, so an
+//! incompatible library is never called with the wrong layouts. The functions that take the lock without blocking
+//! ([`try_with_asar_lock`](crate::try_with_asar_lock), [`with_asar_lock_timeout`](crate::with_asar_lock_timeout) and
+//! [`Patcher::try_apply`](crate::Patcher::try_apply)) and the loading functions of the `dynamic` feature return the
+//! [`AsarError`], the other ones panic with its message. [`check`] returns the error up front, before Asar is used.
+//!
+//! e.g.
+//! ```rust
+//! use asar_snes::{version, Version};
+//!
+//! if let Err(err) = version::check() {
+//!     eprintln!("{}", err);
+//!     return;
+//! }
+//! println!("Asar {}", Version::current().asar_string());
+//! ```
+use core::fmt;
+use std::{mem::size_of, sync::OnceLock};
+
+use crate::{
+    bindings::{asar_apiversion, patchparams},
+    AsarError,
+};
+
+/// The API version of the Asar header the bindings were generated from.
+pub const API_VERSION: i32 = 303;
+
+/// The version of Asar the bindings were generated for.
+pub const VERSION: Version = Version {
+    major: 1,
+    minor: 9,
+    patch: 1,
+};
+
+/// The size of `patchparams` in the header of Asar 1.91, which Asar compares with its `structsize` field.
+const PATCHPARAMS_SIZE: usize = if cfg!(target_pointer_width = "64") {
+    120
+} else {
+    68
+};
+
+/// A version of Asar, as returned by [`version`](fn@crate::version) in the format Major * 10000 + Minor * 100 + Revision.
+///
+/// It is displayed as `major.minor.patch`, e.g. `1.9.1`, see [`Version::asar_string`] for the way Asar displays it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: i32,
+    pub minor: i32,
+    pub patch: i32,
+}
+
+impl Version {
+    /// Parses a version in the format Major * 10000 + Minor * 100 + Revision.
+    pub fn from_raw(raw: i32) -> Version {
+        Version {
+            major: raw / 10000,
+            minor: raw / 100 % 100,
+            patch: raw % 100,
+        }
+    }
+
+    /// Returns the version in the format Major * 10000 + Minor * 100 + Revision.
+    pub fn to_raw(self) -> i32 {
+        self.major * 10000 + self.minor * 100 + self.patch
+    }
+
+    /// Returns the version of the Asar library.
+    pub fn current() -> Version {
+        Version::from_raw(crate::version())
+    }
+
+    /// Formats the version the way Asar displays it, e.g. `1.91` for 1.9.1 and `1.10.1` for 1.10.1.
+    pub fn asar_string(self) -> String {
+        let patch = if self.patch >= 10 || self.minor >= 10 {
+            format!(".{}", self.patch)
+        } else {
+            self.patch.to_string()
+        };
+        format!("{}.{}{}", self.major, self.minor, patch)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Checks a library with API version `found` against the bindings.
+///
+/// Like Asar's own bindings, newer libraries with the same major API version are accepted.
+pub(crate) fn check_api_version(found: i32) -> Result<(), AsarError> {
+    if size_of::<patchparams>() != PATCHPARAMS_SIZE {
+        return Err(AsarError::LayoutMismatch {
+            found: size_of::<patchparams>(),
+            expected: PATCHPARAMS_SIZE,
+        });
+    }
+    if found < API_VERSION || found / 100 > API_VERSION / 100 {
+        return Err(AsarError::IncompatibleVersion {
+            found,
+            expected: API_VERSION,
+        });
+    }
+    Ok(())
+}
+
+/// Checks that the Asar library is compatible with the bindings.
+pub fn check() -> Result<(), AsarError> {
+    check_api_version(unsafe { asar_apiversion() })
+}
+
+/// Runs [`check`] until it succeeds once, loading the library first with the `dynamic` feature.
+pub(crate) fn ensure_compatible() -> Result<(), AsarError> {
+    static COMPATIBLE: OnceLock<()> = OnceLock::new();
+    if COMPATIBLE.get().is_none() {
+        #[cfg(feature = "dynamic")]
+        crate::dynamic::ensure_loaded()?;
+        check()?;
+        let _ = COMPATIBLE.set(());
+    }
+    Ok(())
+}
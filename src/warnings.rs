@@ -0,0 +1,203 @@
+//! The warnings Asar can emit, see [`WarningId`], and the [`WarningPolicy`] to turn them into errors.
+use core::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::{deps::normalize, ErrorData, WarningData};
+
+macro_rules! warning_ids {
+    ($($(#[$doc:meta])* $variant:ident = $errid:literal, $name:literal, $enabled:literal;)*) => {
+        /// A warning defined by Asar 1.91, with its numeric id, its name and whether it is enabled by default.
+        ///
+        /// Can be used with [`PatchOption::SetWarning`](crate::PatchOption::SetWarning) to enable or disable a warning
+        /// without the risk of a typo being silently ignored by Asar.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum WarningId {
+            $($(#[$doc])* $variant,)*
+        }
+
+        impl WarningId {
+            /// All the warnings, in the order of their numeric ids.
+            pub const ALL: &'static [WarningId] = &[$(WarningId::$variant,)*];
+
+            /// Returns the numeric id of the warning, as found in [`ErrorData::errid`](crate::ErrorData::errid).
+            pub fn errid(self) -> i32 {
+                match self {
+                    $(WarningId::$variant => $errid,)*
+                }
+            }
+
+            /// Returns the name of the warning, without the `W` prefix.
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(WarningId::$variant => $name,)*
+                }
+            }
+
+            /// Returns whether Asar enables the warning by default.
+            pub fn enabled_by_default(self) -> bool {
+                match self {
+                    $(WarningId::$variant => $enabled,)*
+                }
+            }
+        }
+    };
+}
+
+// Every `warning_id_*` enumerator of `src/asar/src/asar/warnings.h` in Asar 1.91, in declaration order, numbered from
+// `warning_id_start + 1`. `test_warning_ids_match_header` compares this list with the header of the submodule.
+warning_ids! {
+    /// A relative path was passed to Asar, which resolves it against the working directory.
+    RelativePathUsed = 1001, "relative_path_used", true;
+    RomTooShort = 1002, "rom_too_short", true;
+    RomTitleIncorrect = 1003, "rom_title_incorrect", true;
+    Spc700Assuming8Bit = 1004, "spc700_assuming_8_bit", true;
+    AssumingAddressMode = 1005, "assuming_address_mode", true;
+    SetMiddleByte = 1006, "set_middle_byte", true;
+    UnrecognizedSpecialCommand = 1007, "unrecognized_special_command", true;
+    FreespaceLeaked = 1008, "freespace_leaked", true;
+    /// Emitted by the `warn` command.
+    WarnCommand = 1009, "warn_command", true;
+    ImplicitlySizedImmediate = 1010, "implicitly_sized_immediate", false;
+    XkasDeprecated = 1011, "xkas_deprecated", true;
+    XkasEatParentheses = 1012, "xkas_eat_parentheses", true;
+    XkasLabelAccess = 1013, "xkas_label_access", true;
+    XkasWarnpcRelaxed = 1014, "xkas_warnpc_relaxed", true;
+    XkasStyleConditional = 1015, "xkas_style_conditional", true;
+    XkasPatch = 1016, "xkas_patch", true;
+    XkasIncsrcRelative = 1017, "xkas_incsrc_relative", true;
+    ConvertToAsar = 1018, "convert_to_asar", true;
+    FixedDeprecated = 1019, "fixed_deprecated", true;
+    AutoclearDeprecated = 1020, "autoclear_deprecated", true;
+    /// A file that is not a memory file was accessed.
+    CheckMemoryFile = 1021, "check_memory_file", false;
+    IfNotConditionDeprecated = 1022, "if_not_condition_deprecated", true;
+    FunctionRedefined = 1023, "function_redefined", true;
+    DatasizeLastLabel = 1024, "datasize_last_label", true;
+    DatasizeExceedsSize = 1025, "datasize_exceeds_size", true;
+    MapperAlreadySet = 1026, "mapper_already_set", true;
+    FeatureDeprecated = 1027, "feature_deprecated", true;
+    ByteOrderMarkUtf8 = 1028, "byte_order_mark_utf8", true;
+    OptimizationSettings = 1029, "optimization_settings", true;
+}
+
+impl WarningId {
+    /// Returns the warning with the numeric id `errid`.
+    pub fn from_errid(errid: i32) -> Option<WarningId> {
+        WarningId::ALL.iter().copied().find(|w| w.errid() == errid)
+    }
+
+    /// Parses a warning as written on Asar's command line and in its messages, e.g. `Wrelative_path_used`, `relative_path_used` or `W1001`.
+    pub fn from_name(name: &str) -> Option<WarningId> {
+        let name = name.strip_prefix('W').unwrap_or(name);
+        match name.parse::<i32>() {
+            Ok(errid) => WarningId::from_errid(errid),
+            Err(_) => WarningId::ALL.iter().copied().find(|w| w.name() == name),
+        }
+    }
+}
+
+impl fmt::Display for WarningId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "W{}", self.name())
+    }
+}
+
+/// Decides which warnings fail a patch operation, set with [`PatchOption::WarningPolicy`](crate::PatchOption::WarningPolicy).
+///
+/// When a warning is denied, [`patching::patch_ex`](crate::patching::patch_ex) returns [`PatchResult::Failure`](crate::PatchResult::Failure)
+/// with the denied warnings as errors, and the ROM is not returned.
+///
+/// Allow rules take precedence over deny rules, so a policy can deny every warning except a few, or every warning except the ones
+/// coming from a directory of third party code.
+///
+/// e.g.
+/// ```rust
+/// use asar_snes::warnings::WarningPolicy;
+/// use asar_snes::{AdvancedPatchOptions, PatchOption, WarningId};
+///
+/// let policy = WarningPolicy::new()
+///     .deny_all()
+///     .allow(WarningId::FeatureDeprecated)
+///     .allow_path("asm/vendor");
+/// let options = AdvancedPatchOptions::new().option(PatchOption::WarningPolicy(policy));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WarningPolicy {
+    pub(crate) deny_all: bool,
+    pub(crate) deny: Vec<WarningId>,
+    pub(crate) allow: Vec<WarningId>,
+    pub(crate) allow_paths: Vec<(PathBuf, Option<WarningId>)>,
+}
+
+impl WarningPolicy {
+    /// Creates a new WarningPolicy, which doesn't deny any warning.
+    pub fn new() -> WarningPolicy {
+        WarningPolicy::default()
+    }
+
+    /// Denies every warning, including the ones that are not in [`WarningId`].
+    pub fn deny_all(mut self) -> WarningPolicy {
+        self.deny_all = true;
+        self
+    }
+
+    /// Denies a warning.
+    pub fn deny(mut self, warning: WarningId) -> WarningPolicy {
+        self.deny.push(warning);
+        self
+    }
+
+    /// Allows a warning, even if it is denied by another rule.
+    pub fn allow(mut self, warning: WarningId) -> WarningPolicy {
+        self.allow.push(warning);
+        self
+    }
+
+    /// Allows every warning coming from `path`, which can be a file or a directory.
+    ///
+    /// The path is compared with [`ErrorData::filename`](crate::ErrorData::filename), so it should be relative or absolute
+    /// in the same way as the paths passed to Asar.
+    pub fn allow_path<P: Into<PathBuf>>(mut self, path: P) -> WarningPolicy {
+        self.allow_paths.push((normalize(&path.into()), None));
+        self
+    }
+
+    /// Allows a warning when it comes from `path`, which can be a file or a directory.
+    pub fn allow_in<P: Into<PathBuf>>(mut self, path: P, warning: WarningId) -> WarningPolicy {
+        self.allow_paths
+            .push((normalize(&path.into()), Some(warning)));
+        self
+    }
+
+    /// Returns whether `warning` fails the patch operation.
+    pub fn is_denied(&self, warning: &WarningData) -> bool {
+        let id = warning.warning_id();
+        let denied = self.deny_all || id.is_some_and(|id| self.deny.contains(&id));
+        if !denied || id.is_some_and(|id| self.allow.contains(&id)) {
+            return false;
+        }
+        let filename = normalize(Path::new(&warning.filename));
+        !self.allow_paths.iter().any(|(path, allowed)| {
+            filename.starts_with(path) && (allowed.is_none() || *allowed == id)
+        })
+    }
+
+    /// Returns whether this policy can deny any warning at all.
+    pub(crate) fn can_deny(&self) -> bool {
+        self.deny_all || !self.deny.is_empty()
+    }
+
+    /// Splits `warnings` into the allowed warnings and the denied ones.
+    pub(crate) fn check(
+        &self,
+        warnings: Vec<WarningData>,
+    ) -> Result<Vec<WarningData>, Vec<ErrorData>> {
+        let (denied, allowed): (Vec<_>, Vec<_>) =
+            warnings.into_iter().partition(|w| self.is_denied(w));
+        if denied.is_empty() {
+            Ok(allowed)
+        } else {
+            Err(denied)
+        }
+    }
+}
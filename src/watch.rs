@@ -0,0 +1,247 @@
+//! Watch mode, re-assembling a patch every time one of its files changes.
+//!
+//! The [`Watcher`] polls the modification time of the entry file and of every file it includes, as found by
+//! [`deps::scan`], and re-runs the patch once the files stop changing for the debounce duration.
+//! The set of watched files is recomputed after each build, so new includes are picked up automatically.
+//! Memory files are not watched, since they cannot change during the lifetime of the watcher.
+//!
+//! e.g.
+//! ```rust,no_run
+//! use std::ops::ControlFlow;
+//! use asar_snes::watch::Watcher;
+//! use asar_snes::{Patcher, PatchResult};
+//!
+//! let rom = std::fs::read("base.sfc").unwrap();
+//! Watcher::new(Patcher::new(), rom.into(), "main.asm").run(|event| {
+//!     match &event.result {
+//!         PatchResult::Success(rom, _) => println!("assembled, {} bytes", rom.length),
+//!         PatchResult::Failure(errors) => errors.iter().for_each(|e| println!("{}", e.fullerrdata)),
+//!     }
+//!     ControlFlow::Continue(())
+//! });
+//! ```
+use std::{
+    collections::HashMap,
+    fs,
+    ops::ControlFlow,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{deps, AdvancedPatchOptions, Label, PatchResult, Patcher, RomData};
+
+/// The outcome of a build done by the [`Watcher`].
+#[derive(Debug, Clone)]
+pub struct WatchEvent {
+    /// The result of the patch operation.
+    pub result: PatchResult,
+    /// The labels of the patch, empty if the patch failed.
+    pub labels: Vec<Label>,
+    /// The files that changed since the previous build, empty for the first build.
+    pub changed: Vec<PathBuf>,
+}
+
+/// Re-assembles a patch every time one of its files changes.
+///
+/// See the [module documentation](self) for more information.
+#[derive(Debug, Clone)]
+pub struct Watcher {
+    options: AdvancedPatchOptions,
+    rom: RomData,
+    entry: String,
+    interval: Duration,
+    debounce: Duration,
+}
+
+/// Handle to a [`Watcher`] running on a background thread, see [`Watcher::spawn`].
+#[derive(Debug)]
+pub struct WatchHandle {
+    stop: Arc<AtomicBool>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl WatchHandle {
+    /// Stops the watcher, waiting for the current build to finish.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = self.thread.join();
+    }
+}
+
+type Snapshot = HashMap<PathBuf, Option<SystemTime>>;
+
+impl Watcher {
+    /// Creates a new Watcher, using the options of the `patcher`, which applies the patch at `entry` to a copy of `rom` on every build.
+    pub fn new<T: Into<String>>(patcher: Patcher, rom: RomData, entry: T) -> Watcher {
+        Watcher {
+            options: patcher.options.unwrap_or_default(),
+            rom,
+            entry: entry.into(),
+            interval: Duration::from_millis(200),
+            debounce: Duration::from_millis(100),
+        }
+    }
+
+    /// Sets how often the files are checked for changes, by default every 200ms.
+    pub fn interval(mut self, interval: Duration) -> Watcher {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets how long the files must stay unchanged before a build starts, by default 100ms.
+    ///
+    /// This avoids building multiple times when an editor saves several files at once.
+    pub fn debounce(mut self, debounce: Duration) -> Watcher {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Builds the patch, then keeps rebuilding it every time its files change, calling `callback` with every outcome.
+    ///
+    /// Returns when `callback` returns [`ControlFlow::Break`].
+    ///
+    /// remarks: This function uses the global lock during each build.
+    pub fn run<F>(self, mut callback: F)
+    where
+        F: FnMut(WatchEvent) -> ControlFlow<()>,
+    {
+        self.watch(|| false, &mut callback);
+    }
+
+    /// Runs the watcher on a background thread, sending every outcome to the returned receiver.
+    ///
+    /// The watcher stops when [`WatchHandle::stop`] is called or when the receiver is dropped.
+    pub fn spawn(self) -> (WatchHandle, mpsc::Receiver<WatchEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = thread::spawn(move || {
+            self.watch(
+                || thread_stop.load(Ordering::SeqCst),
+                &mut |event| match sender.send(event) {
+                    Ok(()) => ControlFlow::Continue(()),
+                    Err(_) => ControlFlow::Break(()),
+                },
+            );
+        });
+        (WatchHandle { stop, thread }, receiver)
+    }
+
+    fn watch<S, F>(&self, should_stop: S, callback: &mut F)
+    where
+        S: Fn() -> bool,
+        F: FnMut(WatchEvent) -> ControlFlow<()>,
+    {
+        let mut changed = Vec::new();
+        loop {
+            // taken before the build, so that a file modified while Asar runs triggers another build
+            let files = self.watched_files();
+            let mut previous = snapshot(&files);
+            let event = self.build(std::mem::take(&mut changed));
+            if callback(event).is_break() {
+                return;
+            }
+
+            // wait for a change
+            loop {
+                if should_stop() {
+                    return;
+                }
+                thread::sleep(self.interval);
+                let current = snapshot(&files);
+                if current != previous {
+                    changed = changed_files(&previous, &current);
+                    previous = current;
+                    break;
+                }
+            }
+            // wait for the files to settle
+            let mut last_change = Instant::now();
+            while last_change.elapsed() < self.debounce {
+                if should_stop() {
+                    return;
+                }
+                thread::sleep(self.debounce.min(self.interval));
+                let current = snapshot(&files);
+                if current != previous {
+                    changed.extend(changed_files(&previous, &current));
+                    previous = current;
+                    last_change = Instant::now();
+                }
+            }
+            changed.sort();
+            changed.dedup();
+        }
+    }
+
+    fn build(&self, changed: Vec<PathBuf>) -> WatchEvent {
+        let (result, labels) = crate::with_asar_lock(|| {
+            let result = crate::patching::patch_ex(
+                self.rom.clone(),
+                self.entry.clone(),
+                self.options.clone(),
+            );
+            let labels = match result {
+                PatchResult::Success(_, _) => crate::patching::labels(),
+                PatchResult::Failure(_) => Vec::new(),
+            };
+            (result, labels)
+        });
+        WatchEvent {
+            result,
+            labels,
+            changed,
+        }
+    }
+
+    /// Returns the files the patch depends on.
+    fn watched_files(&self) -> Vec<PathBuf> {
+        let mut files = match deps::scan(&self.entry, &self.options) {
+            Ok(graph) => graph.disk_files().map(PathBuf::from).collect(),
+            Err(_) => Vec::new(),
+        };
+        // the entry is watched even when it is missing, to rebuild once it is created
+        let entry = deps::normalize(self.entry.as_ref());
+        if !files.contains(&entry)
+            && !self
+                .options
+                .memory_files
+                .iter()
+                .any(|f| f.filename == self.entry)
+        {
+            files.push(entry);
+        }
+        files
+    }
+}
+
+/// Returns the modification time of every file, None if the file doesn't exist.
+fn snapshot(files: &[PathBuf]) -> Snapshot {
+    files
+        .iter()
+        .map(|path| {
+            let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+            (path.clone(), modified)
+        })
+        .collect()
+}
+
+fn changed_files(before: &Snapshot, after: &Snapshot) -> Vec<PathBuf> {
+    let mut changed = after
+        .iter()
+        .filter(|(path, modified)| before.get(*path) != Some(modified))
+        .map(|(path, _)| path.clone())
+        .collect::<Vec<_>>();
+    changed.extend(
+        before
+            .keys()
+            .filter(|path| !after.contains_key(*path))
+            .cloned(),
+    );
+    changed
+}
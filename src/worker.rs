@@ -0,0 +1,612 @@
+//! Process-isolated worker pool, running patch operations in helper processes.
+//!
+//! Asar keeps global state, so a process can only run one patch operation at a time. The [`WorkerPool`] spawns up to
+//! N `asar-worker` processes (a small binary shipped by this crate) and sends each patch operation to an idle one,
+//! so N operations can run truly in parallel. If Asar crashes, only the worker dies: the operation returns
+//! [`WorkerError::Crashed`] and a new worker is spawned for the next one.
+//!
+//! The ROM, the options and the memory files are sent to the worker over its stdin, and the result is read back
+//! from its stdout, with the same binary encoding as the patch cache.
+//! Each worker is reset before every operation, so [`PatchOption::ShouldReset`](crate::PatchOption::ShouldReset)
+//! has no effect, and the [`WarningPolicy`](crate::warnings::WarningPolicy) is applied in the calling process.
+//!
+//! The worker binary is found with the `ASAR_WORKER` environment variable, then next to the current executable,
+//! then in the `PATH`. Any executable that calls [`serve`] with its stdin and stdout can be used as a worker.
+//!
+//! Since each operation runs in its own process, a patch that never finishes (an endless `while` loop, a recursive
+//! macro, ...) can be stopped with a [`timeout`](WorkerPool::timeout) or a [`CancellationToken`]: its worker is killed
+//! and the operation returns [`WorkerError::Timeout`] or [`WorkerError::Cancelled`].
+//!
+//! e.g.
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use asar_snes::worker::{WorkerPatchResult, WorkerPool};
+//! use asar_snes::AdvancedPatchOptions;
+//!
+//! let pool = WorkerPool::new(4).timeout(Duration::from_secs(30));
+//! let rom = std::fs::read("base.sfc").unwrap();
+//! let jobs = ["a.asm", "b.asm", "c.asm"]
+//!     .into_iter()
+//!     .map(|patch| (rom.clone().into(), patch.to_string(), AdvancedPatchOptions::new()));
+//! for result in pool.patch_all(jobs) {
+//!     match result {
+//!         Ok(WorkerPatchResult::Success(patch)) => println!("assembled, {} bytes", patch.romdata.length),
+//!         Ok(WorkerPatchResult::Failure(errors)) => println!("{:?}", errors),
+//!         Err(err) => println!("{}", err),
+//!     }
+//! }
+//! ```
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fmt,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    codec::{Decoder, Encoder},
+    patching, AdvancedPatchOptions, Define, ErrorData, Label, MemoryFile, MemoryFileData,
+    PatchResult, RomData, WarnSetting, WarningData, WrittenBlock,
+};
+
+const MAGIC: u32 = u32::from_le_bytes(*b"ASRW");
+const PROTOCOL_VERSION: u32 = 1;
+
+/// How often a running operation checks its cancellation token and deadline.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+const MEMORY_FILE_BINARY: u8 = 0;
+const MEMORY_FILE_TEXT: u8 = 1;
+
+/// The result of a successful patch operation run by a worker.
+#[derive(Debug, Clone)]
+pub struct WorkerPatch {
+    pub romdata: RomData,
+    pub warnings: Vec<WarningData>,
+    pub labels: Vec<Label>,
+    pub defines: Vec<Define>,
+    pub prints: Vec<String>,
+    pub written_blocks: Vec<WrittenBlock>,
+}
+
+/// The result of [`WorkerPool::patch`].
+#[derive(Debug, Clone)]
+pub enum WorkerPatchResult {
+    Success(WorkerPatch),
+    Failure(Vec<ErrorData>),
+}
+
+/// Error returned when a patch operation could not be run by a worker.
+#[derive(Debug)]
+pub enum WorkerError {
+    /// Spawning or communicating with the worker process failed.
+    Io(io::Error),
+    /// The worker process exited during the patch operation, usually because Asar crashed.
+    Crashed(ExitStatus),
+    /// The patch operation took longer than the [`timeout`](WorkerPool::timeout) of the pool, its worker was killed.
+    Timeout(Duration),
+    /// The patch operation was stopped with a [`CancellationToken`].
+    Cancelled,
+}
+
+impl fmt::Display for WorkerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkerError::Io(err) => write!(f, "Asar worker error: {}", err),
+            WorkerError::Crashed(status) => write!(f, "Asar worker crashed: {}", status),
+            WorkerError::Timeout(timeout) => {
+                write!(f, "Patch operation timed out after {:?}", timeout)
+            }
+            WorkerError::Cancelled => write!(f, "Patch operation cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for WorkerError {}
+
+/// A token to cancel patch operations running in a [`WorkerPool`], cloning it gives another handle to the same token.
+///
+/// Once cancelled, every operation using the token is stopped and returns [`WorkerError::Cancelled`],
+/// including the operations started afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a new CancellationToken, not cancelled.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancels the operations using this token, killing their workers.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A pool of worker processes running patch operations in parallel.
+///
+/// The workers are spawned when needed and reused between operations. `patch` can be called from several threads
+/// at once, blocking while all the workers are busy.
+///
+/// See the [module documentation](self) for more information.
+#[derive(Debug)]
+pub struct WorkerPool {
+    program: PathBuf,
+    args: Vec<OsString>,
+    size: usize,
+    timeout: Option<Duration>,
+    state: Mutex<PoolState>,
+    available: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    idle: Vec<Worker>,
+    live: usize,
+}
+
+#[derive(Debug)]
+struct Worker {
+    child: Child,
+    stdin: BufWriter<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+/// Returns the path of the `asar-worker` binary, see the [module documentation](self).
+fn default_program() -> PathBuf {
+    if let Some(program) = env::var_os("ASAR_WORKER") {
+        return program.into();
+    }
+    let name = format!("asar-worker{}", env::consts::EXE_SUFFIX);
+    if let Ok(exe) = env::current_exe() {
+        // tests and examples live one directory below the binaries in cargo's target directory
+        for dir in exe.ancestors().skip(1).take(2) {
+            let program = dir.join(&name);
+            if program.is_file() {
+                return program;
+            }
+        }
+    }
+    PathBuf::from(name)
+}
+
+impl WorkerPool {
+    /// Creates a new WorkerPool with up to `size` workers, at least one.
+    ///
+    /// No process is spawned until the first patch operation.
+    pub fn new(size: usize) -> WorkerPool {
+        WorkerPool {
+            program: default_program(),
+            args: Vec::new(),
+            size: size.max(1),
+            timeout: None,
+            state: Mutex::new(PoolState::default()),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Sets the path of the worker binary.
+    pub fn program<P: Into<PathBuf>>(mut self, program: P) -> WorkerPool {
+        self.program = program.into();
+        self
+    }
+
+    /// Adds an argument to the command line of the workers, e.g. to run the current executable in worker mode.
+    pub fn arg<S: AsRef<OsStr>>(mut self, arg: S) -> WorkerPool {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Sets the maximum wall-clock duration of a patch operation, after which its worker is killed and
+    /// [`WorkerError::Timeout`] is returned.
+    ///
+    /// The time spent waiting for an idle worker is not counted.
+    pub fn timeout(mut self, timeout: Duration) -> WorkerPool {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Returns the maximum number of workers.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Patches the ROM data like [`patching::patch_ex`], in a worker process.
+    ///
+    /// remarks: This function does not use the global lock.
+    pub fn patch<T: Into<String>>(
+        &self,
+        rom: RomData,
+        patch: T,
+        options: AdvancedPatchOptions,
+    ) -> Result<WorkerPatchResult, WorkerError> {
+        self.patch_cancellable(rom, patch, options, &CancellationToken::new())
+    }
+
+    /// Patches the ROM data like [`patch`](WorkerPool::patch), stopping when `cancel` is cancelled.
+    ///
+    /// remarks: This function does not use the global lock.
+    pub fn patch_cancellable<T: Into<String>>(
+        &self,
+        rom: RomData,
+        patch: T,
+        options: AdvancedPatchOptions,
+        cancel: &CancellationToken,
+    ) -> Result<WorkerPatchResult, WorkerError> {
+        if cancel.is_cancelled() {
+            return Err(WorkerError::Cancelled);
+        }
+        let job = encode_job(&rom, &patch.into(), &options).map_err(WorkerError::Io)?;
+        let mut worker = self.checkout(cancel)?;
+        let result = worker.run(&job, rom, self.timeout, cancel);
+        self.checkin(result.is_ok().then_some(worker));
+        Ok(match result? {
+            WorkerPatchResult::Success(mut patch) => {
+                match options.warning_policy.check(patch.warnings) {
+                    Ok(warnings) => {
+                        patch.warnings = warnings;
+                        WorkerPatchResult::Success(patch)
+                    }
+                    Err(denied) => WorkerPatchResult::Failure(denied),
+                }
+            }
+            failure => failure,
+        })
+    }
+
+    /// Runs several patch operations in parallel, returning the results in the same order as the jobs.
+    ///
+    /// One thread per worker takes the jobs from `jobs` as they finish, so the jobs are only read when a worker is free.
+    ///
+    /// remarks: This function does not use the global lock.
+    pub fn patch_all<I>(&self, jobs: I) -> Vec<Result<WorkerPatchResult, WorkerError>>
+    where
+        I: IntoIterator<Item = (RomData, String, AdvancedPatchOptions)>,
+        I::IntoIter: Send,
+    {
+        let jobs = Mutex::new(jobs.into_iter().enumerate());
+        let results = Mutex::new(Vec::new());
+        thread::scope(|scope| {
+            for _ in 0..self.size {
+                scope.spawn(|| loop {
+                    // the lock is released before the job runs
+                    let Some((index, (rom, patch, options))) = jobs.lock().unwrap().next() else {
+                        return;
+                    };
+                    let result = self.patch(rom, patch, options);
+                    results.lock().unwrap().push((index, result));
+                });
+            }
+        });
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Takes an idle worker, spawning one if the pool is not full, or waits for one to be returned.
+    fn checkout(&self, cancel: &CancellationToken) -> Result<Worker, WorkerError> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(worker) = state.idle.pop() {
+                return Ok(worker);
+            }
+            if state.live < self.size {
+                state.live += 1;
+                drop(state);
+                return Worker::spawn(&self.program, &self.args, self.timeout, cancel)
+                    .inspect_err(|_| self.checkin(None));
+            }
+            if cancel.is_cancelled() {
+                return Err(WorkerError::Cancelled);
+            }
+            state = self.available.wait_timeout(state, POLL_INTERVAL).unwrap().0;
+        }
+    }
+
+    /// Returns a worker to the pool, None if it died.
+    fn checkin(&self, worker: Option<Worker>) {
+        let mut state = self.state.lock().unwrap();
+        match worker {
+            Some(worker) => state.idle.push(worker),
+            None => state.live -= 1,
+        }
+        self.available.notify_one();
+    }
+}
+
+impl Worker {
+    /// Starts a worker and reads its handshake, killing it if `cancel` is cancelled or `timeout` elapses first.
+    fn spawn(
+        program: &Path,
+        args: &[OsString],
+        timeout: Option<Duration>,
+        cancel: &CancellationToken,
+    ) -> Result<Worker, WorkerError> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(WorkerError::Io)?;
+        let mut worker = Worker {
+            stdin: BufWriter::new(child.stdin.take().unwrap()),
+            stdout: BufReader::new(child.stdout.take().unwrap()),
+            child,
+        };
+        let handshake = worker.interruptible(timeout, cancel, |_, stdout| {
+            let mut decoder = Decoder::new(stdout);
+            Ok((decoder.u32()?, decoder.u32()?))
+        })?;
+        match handshake {
+            (MAGIC, PROTOCOL_VERSION) => Ok(worker),
+            _ => Err(worker.fail(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a compatible asar worker", program.display()),
+            ))),
+        }
+    }
+
+    /// Sends a job to the worker and reads its result, killing the worker if the job is cancelled or times out.
+    fn run(
+        &mut self,
+        job: &[u8],
+        rom: RomData,
+        timeout: Option<Duration>,
+        cancel: &CancellationToken,
+    ) -> Result<WorkerPatchResult, WorkerError> {
+        self.interruptible(timeout, cancel, |stdin, stdout| {
+            stdin.write_all(job)?;
+            stdin.flush()?;
+            read_result(&mut Decoder::new(stdout), rom)
+        })
+    }
+
+    /// Talks to the worker with `f`, killing the worker if `cancel` is cancelled or `timeout` elapses first.
+    ///
+    /// The communication happens on another thread, so that this one can watch the token and the deadline.
+    fn interruptible<T, F>(
+        &mut self,
+        timeout: Option<Duration>,
+        cancel: &CancellationToken,
+        f: F,
+    ) -> Result<T, WorkerError>
+    where
+        T: Send,
+        F: FnOnce(&mut BufWriter<ChildStdin>, &mut BufReader<ChildStdout>) -> io::Result<T> + Send,
+    {
+        let deadline = timeout.map(|timeout| (Instant::now() + timeout, timeout));
+        let (stdin, stdout) = (&mut self.stdin, &mut self.stdout);
+        let result = thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            scope.spawn(move || {
+                let _ = sender.send(f(stdin, stdout));
+            });
+            loop {
+                match receiver.recv_timeout(POLL_INTERVAL) {
+                    Ok(result) => return Ok(result),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => {
+                        unreachable!("the worker thread always sends its result")
+                    }
+                }
+                let interrupted = if cancel.is_cancelled() {
+                    WorkerError::Cancelled
+                } else if let Some((_, timeout)) =
+                    deadline.filter(|(deadline, _)| Instant::now() >= *deadline)
+                {
+                    WorkerError::Timeout(timeout)
+                } else {
+                    continue;
+                };
+                // unblocks the worker thread, which then fails to read the result
+                let _ = self.child.kill();
+                return Err(interrupted);
+            }
+        });
+        match result {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(err)) => Err(self.fail(err)),
+            Err(interrupted) => {
+                let _ = self.child.wait();
+                Err(interrupted)
+            }
+        }
+    }
+
+    /// Turns an error while talking to the worker into a [`WorkerError`], killing the worker.
+    fn fail(&mut self, err: io::Error) -> WorkerError {
+        let _ = self.child.kill();
+        match self.child.wait() {
+            Ok(status)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::UnexpectedEof | io::ErrorKind::BrokenPipe
+                ) =>
+            {
+                WorkerError::Crashed(status)
+            }
+            _ => WorkerError::Io(err),
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Encodes a patch operation, only the first `rom.length` bytes of the ROM are sent.
+pub(crate) fn encode_job(
+    rom: &RomData,
+    patch: &str,
+    options: &AdvancedPatchOptions,
+) -> io::Result<Vec<u8>> {
+    let mut encoder = Encoder::new(Vec::new());
+    encoder.u64(rom.data.len() as u64)?;
+    encoder.bytes(&rom.data[..rom.length])?;
+    encoder.str(patch)?;
+    encoder.list(&options.includepaths, |e, path| e.str(path))?;
+    encoder.list(&options.additional_defines, Encoder::define)?;
+    for file in [&options.stdincludesfile, &options.stddefinesfile] {
+        encoder.bool(file.is_some())?;
+        encoder.str(file.as_deref().unwrap_or_default())?;
+    }
+    encoder.list(&options.warning_settings, |e, setting| {
+        e.str(&setting.warnid)?;
+        e.bool(setting.enabled)
+    })?;
+    encoder.list(&options.memory_files, |e, file| {
+        e.str(&file.filename)?;
+        match &file.data {
+            MemoryFileData::Binary(data) => {
+                e.u8(MEMORY_FILE_BINARY)?;
+                e.bytes(data)
+            }
+            MemoryFileData::Text(text) => {
+                e.u8(MEMORY_FILE_TEXT)?;
+                e.str(text)
+            }
+        }
+    })?;
+    encoder.bool(options.override_checksum_gen)?;
+    encoder.bool(options.generate_checksum)?;
+    Ok(encoder.into_inner())
+}
+
+fn decode_job<R: Read>(
+    decoder: &mut Decoder<R>,
+) -> io::Result<(RomData, String, AdvancedPatchOptions)> {
+    let buflen = decoder.u64()? as usize;
+    let mut data = decoder.bytes()?;
+    let length = data.len();
+    if length > buflen {
+        return Err(io::ErrorKind::InvalidData.into());
+    }
+    data.resize(buflen, 0);
+    let patch = decoder.string()?;
+
+    let mut options = AdvancedPatchOptions::new();
+    options.includepaths = decoder.list(Decoder::string)?;
+    options.additional_defines = decoder.list(Decoder::define)?;
+    for file in [&mut options.stdincludesfile, &mut options.stddefinesfile] {
+        let present = decoder.bool()?;
+        let path = decoder.string()?;
+        *file = present.then_some(path);
+    }
+    options.warning_settings = decoder.list(|d| {
+        Ok(WarnSetting {
+            warnid: d.string()?,
+            enabled: d.bool()?,
+        })
+    })?;
+    options.memory_files = decoder.list(|d| {
+        let filename = d.string()?;
+        let data = match d.u8()? {
+            MEMORY_FILE_BINARY => MemoryFileData::Binary(d.bytes()?),
+            MEMORY_FILE_TEXT => MemoryFileData::Text(d.string()?),
+            _ => return Err(io::ErrorKind::InvalidData.into()),
+        };
+        Ok(MemoryFile { filename, data })
+    })?;
+    options.override_checksum_gen = decoder.bool()?;
+    options.generate_checksum = decoder.bool()?;
+    Ok((RomData { data, length }, patch, options))
+}
+
+fn write_result<W: Write>(encoder: &mut Encoder<W>, result: &WorkerPatchResult) -> io::Result<()> {
+    match result {
+        WorkerPatchResult::Success(patch) => {
+            encoder.bool(true)?;
+            encoder.bytes(&patch.romdata.data[..patch.romdata.length])?;
+            encoder.list(&patch.warnings, Encoder::error)?;
+            encoder.list(&patch.labels, Encoder::label)?;
+            encoder.list(&patch.defines, Encoder::define)?;
+            encoder.list(&patch.prints, |e, print| e.str(print))?;
+            encoder.list(&patch.written_blocks, Encoder::written_block)
+        }
+        WorkerPatchResult::Failure(errors) => {
+            encoder.bool(false)?;
+            encoder.list(errors, Encoder::error)
+        }
+    }
+}
+
+/// Reads the result of a patch operation, the ROM data is rebuilt from the input ROM, since Asar never writes past
+/// the new ROM length.
+pub(crate) fn read_result<R: Read>(
+    decoder: &mut Decoder<R>,
+    mut rom: RomData,
+) -> io::Result<WorkerPatchResult> {
+    if !decoder.bool()? {
+        return Ok(WorkerPatchResult::Failure(decoder.list(Decoder::error)?));
+    }
+    let written = decoder.bytes()?;
+    if written.len() > rom.data.len() {
+        return Err(io::ErrorKind::InvalidData.into());
+    }
+    rom.data[..written.len()].copy_from_slice(&written);
+    rom.length = written.len();
+    Ok(WorkerPatchResult::Success(WorkerPatch {
+        romdata: rom,
+        warnings: decoder.list(Decoder::error)?,
+        labels: decoder.list(Decoder::label)?,
+        defines: decoder.list(Decoder::define)?,
+        prints: decoder.list(Decoder::string)?,
+        written_blocks: decoder.list(Decoder::written_block)?,
+    }))
+}
+
+/// Runs the worker side of the protocol, reading patch operations from `input` and writing the results to `output`
+/// until `input` is closed.
+///
+/// This is the whole `asar-worker` binary, it can be called from another executable to use it as a worker.
+///
+/// remarks: This function uses the global lock.
+pub fn serve<R: Read, W: Write>(input: R, output: W) -> io::Result<()> {
+    let mut decoder = Decoder::new(BufReader::new(input));
+    let mut encoder = Encoder::new(BufWriter::new(output));
+    encoder.u32(MAGIC)?;
+    encoder.u32(PROTOCOL_VERSION)?;
+    encoder.flush()?;
+    loop {
+        let (rom, patch, options) = match decode_job(&mut decoder) {
+            Ok(job) => job,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let result = crate::with_asar_lock(|| {
+            patching::reset();
+            match patching::patch_ex(rom, patch, options) {
+                PatchResult::Success(romdata, warnings) => {
+                    WorkerPatchResult::Success(WorkerPatch {
+                        romdata,
+                        warnings,
+                        labels: patching::labels(),
+                        defines: patching::defines(),
+                        prints: patching::prints(),
+                        written_blocks: patching::written_blocks(),
+                    })
+                }
+                PatchResult::Failure(errors) => WorkerPatchResult::Failure(errors),
+            }
+        });
+        write_result(&mut encoder, &result)?;
+        encoder.flush()?;
+    }
+}